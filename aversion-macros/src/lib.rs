@@ -7,53 +7,326 @@ use proc_macro::TokenStream;
 use proc_macro2::Ident;
 use quote::{format_ident, quote};
 use syn::parse::{Parse, ParseStream};
-use syn::{parse_macro_input, punctuated::Punctuated, DeriveInput, LitInt, Path, Token, Variant};
+use syn::{
+    parenthesized, parse_macro_input, punctuated::Punctuated, Attribute, DeriveInput, LitInt, Path,
+    Token, Variant,
+};
 
-/// Information extracted from the name of a struct.
+/// Version information about a struct.
+///
+/// This is either extracted from the name of a struct, or from
+/// an `#[aversion(base = Foo, version = 3)]` attribute.
 struct NameInfo {
     struct_name: Ident,
     struct_base: Ident,
     struct_version: u16,
+    /// All supported versions, in ascending order.
+    ///
+    /// The last element is always `struct_version`.
+    versions: Vec<u16>,
 }
 
-/// `str::rsplit_once` function is introduced in Rust v1.52. This provides
-/// the same functionality until v1.52 is available widely enough that we
-/// can require it.
-fn rsplit_once<'a>(s: &'a str, delimiter: char) -> Option<(&'a str, &'a str)> {
-    let split_pos = s.rfind(delimiter)?;
+impl NameInfo {
+    /// Extract version information from a struct.
+    ///
+    /// If the struct has an `#[aversion(...)]` attribute specifying the
+    /// base and version, those will be used. Otherwise, the struct name
+    /// must follow the `Name` + `V` + `{integer}` naming convention.
+    fn from_attrs(ident: &Ident, attrs: &AversionAttrs) -> syn::Result<Self> {
+        let (struct_base, struct_version) = match (&attrs.base, attrs.version) {
+            (Some(struct_base), Some(struct_version)) => (struct_base.clone(), struct_version),
+            (None, None) => Self::split_name(ident)?,
+            _ => {
+                return Err(syn::Error::new(
+                    ident.span(),
+                    "`base` and `version` must be specified together",
+                ))
+            }
+        };
+        let versions = attrs.supported_versions(ident, struct_version)?;
+
+        Ok(NameInfo {
+            struct_name: ident.clone(),
+            struct_base,
+            struct_version,
+            versions,
+        })
+    }
+
+    fn split_name(ident: &Ident) -> syn::Result<(Ident, u16)> {
+        let struct_name_string = ident.to_string();
+        let name_error = || {
+            syn::Error::new(
+                ident.span(),
+                "failed to parse struct name into base+version \
+                (expected a name like `FooV1`, or `#[aversion(base = Foo, version = 1)]`)",
+            )
+        };
+
+        // Split the struct into base and version fields
+        let (base, version) = struct_name_string.rsplit_once('V').ok_or_else(name_error)?;
+        if base.is_empty() {
+            return Err(name_error());
+        }
+        let base = Ident::new(base, ident.span());
+        let version: u16 = version.parse().map_err(|_| name_error())?;
+        if version == 0 {
+            return Err(syn::Error::new(ident.span(), "versions must start at 1"));
+        }
+        Ok((base, version))
+    }
 
-    let a = &s[..split_pos];
-    let b = &s[(split_pos + 1)..];
+    /// The name of the struct with a particular version.
+    ///
+    /// The current version is the struct itself; older versions are
+    /// assumed to follow the naming convention, e.g. `FooV2`.
+    fn version_ident(&self, version: u16) -> Ident {
+        if version == self.struct_version {
+            self.struct_name.clone()
+        } else {
+            versioned_name(&self.struct_base, version)
+        }
+    }
 
-    Some((a, b))
+    /// The supported versions that an upgrade from `version` passes through.
+    ///
+    /// The first element is `version`, and the last element is the
+    /// latest version.
+    fn upgrade_path(&self, version: u16) -> &[u16] {
+        let start = self
+            .versions
+            .iter()
+            .position(|&v| v == version)
+            .expect("unsupported version");
+        &self.versions[start..]
+    }
 }
 
-impl NameInfo {
-    fn from_name(ident: &Ident) -> Self {
-        let struct_name = ident.clone();
-        let struct_name_string = struct_name.to_string();
+/// Arguments parsed from `#[aversion(...)]` attributes.
+#[derive(Debug, Default)]
+struct AversionAttrs {
+    base: Option<Ident>,
+    version: Option<u16>,
+    fallible: bool,
+    versions: Option<Vec<LitInt>>,
+    min_version: Option<LitInt>,
+    id: Option<u16>,
+    unknown: bool,
+    skip_unknown: bool,
+}
 
-        // Split the struct into base and version fields
-        let (struct_base, struct_version) = match rsplit_once(&struct_name_string, 'V') {
-            Some((base, version)) => {
-                if base.is_empty() {
-                    panic!("failed to parse struct name");
+impl AversionAttrs {
+    /// Parse the `#[aversion(...)]` attributes on an item.
+    ///
+    /// Arguments that don't apply to `target`, and arguments that are
+    /// repeated, are errors.
+    fn from_attributes(attrs: &[Attribute], target: AttrTarget) -> syn::Result<Self> {
+        let mut result = AversionAttrs::default();
+        let mut seen = Vec::new();
+        for attr in attrs {
+            if !attr.path.is_ident("aversion") {
+                continue;
+            }
+            let args = attr.parse_args_with(Punctuated::<NamedArg, Token![,]>::parse_terminated)?;
+            for NamedArg { name, arg } in args {
+                if arg.target() != target {
+                    return Err(syn::Error::new_spanned(
+                        &name,
+                        format!(
+                            "`{}` can only be used on {}",
+                            name,
+                            arg.target().description()
+                        ),
+                    ));
+                }
+                if seen.contains(&name) {
+                    return Err(syn::Error::new_spanned(
+                        &name,
+                        format!("duplicate `{}` attribute", name),
+                    ));
                 }
-                let base = Ident::new(&base, ident.span());
-                let version: u16 = version.parse().expect("failed to parse struct version");
-                (base, version)
+                seen.push(name);
+                match arg {
+                    AversionArg::Base(base) => result.base = Some(base),
+                    AversionArg::Version(version) => result.version = Some(version),
+                    AversionArg::Fallible => result.fallible = true,
+                    AversionArg::Versions(versions) => result.versions = Some(versions),
+                    AversionArg::MinVersion(min) => result.min_version = Some(min),
+                    AversionArg::Id(id) => result.id = Some(id),
+                    AversionArg::Unknown => result.unknown = true,
+                    AversionArg::SkipUnknown => result.skip_unknown = true,
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Determine which versions are supported.
+    ///
+    /// By default, every version from 1 to `latest` is supported. This can
+    /// be changed with `#[aversion(versions(3, 4, 6))]` or
+    /// `#[aversion(min_version = 3)]`.
+    fn supported_versions(&self, ident: &Ident, latest: u16) -> syn::Result<Vec<u16>> {
+        // Parse a version literal, and check that it's not newer than the latest.
+        let parse_version = |lit: &LitInt| -> syn::Result<u16> {
+            let version = parse_nonzero_version(lit)?;
+            if version > latest {
+                return Err(syn::Error::new(
+                    lit.span(),
+                    format!(
+                        "version {} is newer than the latest version {}",
+                        version, latest
+                    ),
+                ));
             }
-            None => panic!("failed to parse struct name into base+version"),
+            Ok(version)
         };
 
-        NameInfo {
-            struct_name,
-            struct_base,
-            struct_version,
+        match (&self.versions, &self.min_version) {
+            (Some(_), Some(_)) => Err(syn::Error::new(
+                ident.span(),
+                "`versions` and `min_version` cannot be used together",
+            )),
+            (Some(list), None) => {
+                let mut versions = list
+                    .iter()
+                    .map(parse_version)
+                    .collect::<syn::Result<Vec<_>>>()?;
+                // The latest version is always supported.
+                versions.push(latest);
+                versions.sort_unstable();
+                versions.dedup();
+                Ok(versions)
+            }
+            (None, Some(min)) => {
+                let min = parse_version(min)?;
+                Ok((min..=latest).collect())
+            }
+            (None, None) => Ok((1..=latest).collect()),
+        }
+    }
+}
+
+/// The kind of item that an `#[aversion(...)]` attribute is used on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AttrTarget {
+    /// A versioned struct (or enum).
+    Versioned,
+    /// A message group enum.
+    Group,
+    /// A variant of a message group enum.
+    Variant,
+}
+
+impl AttrTarget {
+    fn description(self) -> &'static str {
+        match self {
+            AttrTarget::Versioned => "a versioned type",
+            AttrTarget::Group => "a message group enum",
+            AttrTarget::Variant => "a message group variant",
         }
     }
 }
 
+/// A single argument inside an `#[aversion(...)]` attribute, and its name.
+struct NamedArg {
+    name: Ident,
+    arg: AversionArg,
+}
+
+impl Parse for NamedArg {
+    fn parse(input: ParseStream) -> syn::parse::Result<Self> {
+        let name: Ident = input.parse()?;
+        let arg = AversionArg::parse_value(&name, input)?;
+        Ok(NamedArg { name, arg })
+    }
+}
+
+/// A single argument inside an `#[aversion(...)]` attribute.
+enum AversionArg {
+    /// `base = Foo`
+    Base(Ident),
+    /// `version = 3`
+    Version(u16),
+    /// `fallible`
+    Fallible,
+    /// `versions(3, 4, 6)`
+    Versions(Vec<LitInt>),
+    /// `min_version = 3`
+    MinVersion(LitInt),
+    /// `id = 123` (on an enum variant)
+    Id(u16),
+    /// `unknown` (on an enum variant)
+    Unknown,
+    /// `skip_unknown` (on an enum)
+    SkipUnknown,
+}
+
+impl AversionArg {
+    /// The kind of item this argument may be used on.
+    fn target(&self) -> AttrTarget {
+        match self {
+            AversionArg::Base(_)
+            | AversionArg::Version(_)
+            | AversionArg::Fallible
+            | AversionArg::Versions(_)
+            | AversionArg::MinVersion(_) => AttrTarget::Versioned,
+            AversionArg::SkipUnknown => AttrTarget::Group,
+            AversionArg::Id(_) | AversionArg::Unknown => AttrTarget::Variant,
+        }
+    }
+
+    /// Parse the rest of an argument, after its name.
+    fn parse_value(name: &Ident, input: ParseStream) -> syn::parse::Result<Self> {
+        if name == "fallible" {
+            return Ok(AversionArg::Fallible);
+        }
+        if name == "unknown" {
+            return Ok(AversionArg::Unknown);
+        }
+        if name == "skip_unknown" {
+            return Ok(AversionArg::SkipUnknown);
+        }
+        if name == "versions" {
+            let content;
+            parenthesized!(content in input);
+            let list = Punctuated::<LitInt, Token![,]>::parse_terminated(&content)?;
+            return Ok(AversionArg::Versions(list.into_iter().collect()));
+        }
+        input.parse::<Token![=]>()?;
+        if name == "base" {
+            Ok(AversionArg::Base(input.parse()?))
+        } else if name == "version" {
+            let lit: LitInt = input.parse()?;
+            Ok(AversionArg::Version(parse_nonzero_version(&lit)?))
+        } else if name == "min_version" {
+            Ok(AversionArg::MinVersion(input.parse()?))
+        } else if name == "id" {
+            let lit: LitInt = input.parse()?;
+            Ok(AversionArg::Id(parse_message_id(&lit)?))
+        } else {
+            Err(syn::Error::new(name.span(), "unknown aversion attribute"))
+        }
+    }
+}
+
+/// Parse a version number literal.
+fn parse_nonzero_version(lit: &LitInt) -> syn::Result<u16> {
+    let version: u16 = lit
+        .base10_parse()
+        .map_err(|_| syn::Error::new(lit.span(), "version must fit in a u16"))?;
+    if version == 0 {
+        return Err(syn::Error::new(lit.span(), "versions must start at 1"));
+    }
+    Ok(version)
+}
+
+/// Parse a message id literal.
+fn parse_message_id(lit: &LitInt) -> syn::Result<u16> {
+    lit.base10_parse()
+        .map_err(|_| syn::Error::new(lit.span(), "message id must fit in a u16"))
+}
+
 fn versioned_name(base: &Ident, version: u16) -> Ident {
     let name = format!("{}V{}", base, version);
     Ident::new(&name, base.span())
@@ -61,7 +334,11 @@ fn versioned_name(base: &Ident, version: u16) -> Ident {
 
 /// Derive the `Versioned` trait on a struct.
 ///
-#[proc_macro_derive(Versioned)]
+/// The base type and version are extracted from the struct name, e.g.
+/// `FooV3` has base `Foo` and version 3. Alternatively, they can be
+/// specified with an attribute: `#[aversion(base = Foo, version = 3)]`.
+///
+#[proc_macro_derive(Versioned, attributes(aversion))]
 pub fn derive_versioned(input: TokenStream) -> TokenStream {
     // parse the input into a DeriveInput syntax tree
     let input = parse_macro_input!(input as DeriveInput);
@@ -70,7 +347,13 @@ pub fn derive_versioned(input: TokenStream) -> TokenStream {
         struct_name,
         struct_base,
         struct_version,
-    } = NameInfo::from_name(&input.ident);
+        ..
+    } = match AversionAttrs::from_attributes(&input.attrs, AttrTarget::Versioned)
+        .and_then(|attrs| NameInfo::from_attrs(&input.ident, &attrs))
+    {
+        Ok(info) => info,
+        Err(e) => return e.to_compile_error().into(),
+    };
 
     // The original generic parameters from the input struct
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
@@ -109,37 +392,91 @@ pub fn derive_versioned(input: TokenStream) -> TokenStream {
 /// It is further assumed that a type alias `Foo` exists and is equivalent
 /// to the latest version. In other words: `type Foo = FooV3`
 ///
-#[proc_macro_derive(UpgradeLatest)]
+/// If the struct has an `#[aversion(base = Foo, version = 3)]` attribute,
+/// the struct itself is used as version 3, and older versions are still
+/// expected to be named `FooV1` and `FooV2` (a type alias is sufficient).
+///
+/// If only some versions are supported, they can be listed with
+/// `#[aversion(versions(3, 4, 6))]`, or `#[aversion(min_version = 3)]`.
+/// Only those versions need to exist; each upgrade goes from one listed
+/// version to the next (e.g. `FooV4` to `FooV6`), and any other version
+/// is reported through `DataSource::unknown_version`.
+///
+/// Each upgrade step uses `TryFromVersion`, so upgrades may be fallible;
+/// a failed upgrade is reported through `DataSource::migration_failed`.
+/// If any upgrade is fallible, add the `#[aversion(fallible)]` attribute,
+/// which disables the generated `FromVersion` impls that skip directly
+/// from older versions to the latest.
+///
+#[proc_macro_derive(UpgradeLatest, attributes(aversion))]
 pub fn derive_upgrade_latest(input: TokenStream) -> TokenStream {
     // parse the input into a DeriveInput syntax tree
     let input = parse_macro_input!(input as DeriveInput);
 
-    let NameInfo {
-        struct_name,
-        struct_base,
-        struct_version,
-    } = NameInfo::from_name(&input.ident);
+    let attrs = match AversionAttrs::from_attributes(&input.attrs, AttrTarget::Versioned) {
+        Ok(attrs) => attrs,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let name_info = match NameInfo::from_attrs(&input.ident, &attrs) {
+        Ok(info) => info,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let struct_name = &name_info.struct_name;
+    let struct_base = &name_info.struct_base;
 
     // The original generic parameters from the input struct
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
-    // Create a list of (version, StructVx), one for each version between 1 and this.
-    let all_versions = (1..=struct_version)
-        .into_iter()
-        .map(|ii| (ii, versioned_name(&struct_base, ii)))
+    let supported_versions = &name_info.versions;
+
+    // Generate the match arm tokens for each supported version.
+    let read_message_arms = name_info
+        .versions
+        .iter()
+        .map(|&ii| quote_read_message_arm(&name_info, ii, false))
         .collect::<Vec<_>>();
 
-    // Generate the match arm tokens for each version.
-    let read_message_arms = all_versions
+    // Generate the async equivalent. It's only kept if the `aversion`
+    // crate's async feature is enabled.
+    let read_message_arms_async = name_info
+        .versions
         .iter()
-        .map(|(v, n)| quote_read_message_arm(*v, n, &struct_name));
+        .map(|&ii| quote_read_message_arm(&name_info, ii, true))
+        .collect::<Vec<_>>();
+    let upgrade_latest_async = quote! {
+        _aversion::__async_items! {
+            #[automatically_derived]
+            impl #impl_generics _aversion::group::AsyncUpgradeLatest
+            for #struct_name #ty_generics #where_clause {
+                async fn upgrade_latest_async<Src>(src: &mut Src, header: Src::Header) -> ::core::result::Result<Self, Src::Error>
+                where
+                    Src: _aversion::group::AsyncDataSource,
+                {
+                    use _aversion::group::GroupHeader;
+
+                    let ver = header.msg_ver();
+                    match ver {
+                        #(#read_message_arms_async)*
+
+                        _ => Err(src.unknown_version::<#struct_base>(ver)),
+                    }
+                }
+            }
+        }
+    };
 
     // Generate the FromVersion impls that skip intermediate versions,
-    // and jump directly to the latest.
-    let all_hops = (1..struct_version - 1)
-        .into_iter()
-        .map(|ii| quote_from_version_hop(&struct_base, ii, struct_version))
-        .collect::<Vec<_>>();
+    // and jump directly to the latest. This isn't possible if some of
+    // the upgrades are fallible.
+    let all_hops = if attrs.fallible {
+        Vec::new()
+    } else {
+        name_info
+            .versions
+            .iter()
+            .map(|&ii| quote_from_version_hop(&name_info, ii))
+            .collect::<Vec<_>>()
+    };
 
     let expanded = quote! {
         #[doc(hidden)]
@@ -158,7 +495,7 @@ pub fn derive_upgrade_latest(input: TokenStream) -> TokenStream {
             impl #impl_generics _aversion::group::UpgradeLatest
             for #struct_name #ty_generics #where_clause {
 
-                fn upgrade_latest<Src>(src: &mut Src, header: Src::Header) -> ::std::result::Result<Self, Src::Error>
+                fn upgrade_latest<Src>(src: &mut Src, header: Src::Header) -> ::core::result::Result<Self, Src::Error>
                 where
                     Src: _aversion::group::DataSource,
                 {
@@ -171,8 +508,14 @@ pub fn derive_upgrade_latest(input: TokenStream) -> TokenStream {
                         _ => Err(src.unknown_version::<#struct_base>(ver)),
                     }
                 }
+
+                fn supports_version(ver: u16) -> bool {
+                    matches!(ver, #(#supported_versions)|*)
+                }
             }
 
+            #upgrade_latest_async
+
             #(#all_hops)*
         };
     };
@@ -180,58 +523,90 @@ pub fn derive_upgrade_latest(input: TokenStream) -> TokenStream {
     expanded.into()
 }
 
+/// Generate a match arm that reads one version and upgrades it to the latest.
+///
+/// The upgrade is done one version at a time, using `TryFromVersion`, so
+/// that both fallible and infallible upgrades are supported.
+///
 fn quote_read_message_arm(
+    name_info: &NameInfo,
     version: u16,
-    versioned_name: &Ident,
-    target_name: &Ident,
+    is_async: bool,
 ) -> proc_macro2::TokenStream {
+    let versioned_name = name_info.version_ident(version);
+    let base = &name_info.struct_base;
+    let await_ = quote_await(is_async);
+
+    let upgrade_chain = name_info
+        .upgrade_path(version)
+        .windows(2)
+        .map(|pair| {
+            let ident_ii = name_info.version_ident(pair[0]);
+            let ident_jj = name_info.version_ident(pair[1]);
+            quote! {
+                let msg = <#ident_jj as _aversion::TryFromVersion<#ident_ii>>::try_from_version(msg)
+                    .map_err(|e| src.migration_failed::<#base, _>(ver, e))?;
+            }
+        })
+        .collect::<Vec<_>>();
+
     quote! {
         #version => {
-            let msg = src.read_message::<#versioned_name>(&header)?;
-            let upgraded = <#target_name as _aversion::FromVersion::<#versioned_name>>::from_version(msg);
-            Ok(upgraded)
+            let msg = src.read_message::<#versioned_name>(&header)#await_?;
+            #(#upgrade_chain)*
+            Ok(msg)
         }
     }
 }
 
+/// Generate `.await`, if generating async code.
+fn quote_await(is_async: bool) -> proc_macro2::TokenStream {
+    if is_async {
+        quote! { .await }
+    } else {
+        quote! {}
+    }
+}
+
+// Create identifiers like `v1`, `v2`, etc.
+fn tmp_ident(x: u16) -> Ident {
+    format_ident!("v{}", x)
+}
+
 /// Chain FromVersion implementations to skip directly to the latest version.
 ///
 /// If there is a FooV1..FooV4, and there is a FromVersion for each N to N+1,
 /// generate the code for `FromVersion<FooV1> for FooV4`.
 ///
-fn quote_from_version_hop(base: &Ident, lo: u16, hi: u16) -> proc_macro2::TokenStream {
-    assert!(hi > lo);
-    if hi - lo < 2 {
+fn quote_from_version_hop(name_info: &NameInfo, lo: u16) -> proc_macro2::TokenStream {
+    let path = name_info.upgrade_path(lo);
+    if path.len() < 3 {
         // The user should already have provided FromVersion<___N> for ___M
         return quote! {};
     }
 
-    // Create identifiers like `v1`, `v2`, etc.
-    fn tmp_ident(x: u16) -> Ident {
-        format_ident!("v{}", x)
-    }
-
     // Create a chain of upgrades.
-    let upgrade_chain = (lo..hi)
-        .into_iter()
-        .map(|ii| {
-            let jj = ii + 1;
-            let tmp_ii = tmp_ident(ii);
-            let tmp_jj = tmp_ident(jj);
-            let ident_jj = versioned_name(base, jj);
+    let upgrade_chain = path
+        .windows(2)
+        .map(|pair| {
+            let tmp_ii = tmp_ident(pair[0]);
+            let tmp_jj = tmp_ident(pair[1]);
+            let ident_ii = name_info.version_ident(pair[0]);
+            let ident_jj = name_info.version_ident(pair[1]);
             quote! {
-                let #tmp_jj = #ident_jj::from_version(#tmp_ii);
+                let #tmp_jj = <#ident_jj as _aversion::FromVersion<#ident_ii>>::from_version(#tmp_ii);
             }
         })
         .collect::<Vec<_>>();
 
-    let lo_ident = versioned_name(base, lo);
-    let hi_ident = versioned_name(base, hi);
+    let hi = name_info.struct_version;
+    let lo_ident = name_info.version_ident(lo);
+    let hi_ident = name_info.version_ident(hi);
     let lo_tmp = tmp_ident(lo);
     let hi_tmp = tmp_ident(hi);
 
     quote! {
-        impl FromVersion<#lo_ident> for #hi_ident {
+        impl _aversion::FromVersion<#lo_ident> for #hi_ident {
             fn from_version(#lo_tmp: #lo_ident) -> Self {
                 #(#upgrade_chain)*
                 #hi_tmp
@@ -240,12 +615,120 @@ fn quote_from_version_hop(base: &Ident, lo: u16, hi: u16) -> proc_macro2::TokenS
     }
 }
 
+/// Derive the `DowngradeTo` trait on a struct.
+///
+/// This macro should be used on the latest version of a struct. It is
+/// assumed that `DowngradeTo` has been implemented for each version `N+1`
+/// to version `N`. This macro will chain those implementations, so that
+/// the latest version can be downgraded to any older version, e.g.
+/// `DowngradeTo<FooV1> for FooV4`.
+///
+/// Like `UpgradeLatest`, it is assumed that all versions 1..N exist,
+/// unless the supported versions are specified with
+/// `#[aversion(versions(...))]` or `#[aversion(min_version = ...)]`.
+///
+#[proc_macro_derive(DowngradeTo, attributes(aversion))]
+pub fn derive_downgrade_to(input: TokenStream) -> TokenStream {
+    // parse the input into a DeriveInput syntax tree
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let name_info = match AversionAttrs::from_attributes(&input.attrs, AttrTarget::Versioned)
+        .and_then(|attrs| NameInfo::from_attrs(&input.ident, &attrs))
+    {
+        Ok(info) => info,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    // Generate the DowngradeTo impls that skip intermediate versions,
+    // and jump directly from the latest.
+    let all_hops = name_info
+        .versions
+        .iter()
+        .map(|&ii| quote_downgrade_hop(&name_info, ii))
+        .collect::<Vec<_>>();
+
+    let expanded = quote! {
+        #[doc(hidden)]
+        #[allow(
+            non_upper_case_globals,
+            unused_attributes,
+            unused_qualifications,
+            non_camel_case_types,
+            non_snake_case
+        )]
+        const _: () = {
+            #[allow(rust_2018_idioms, clippy::useless_attribute)]
+            extern crate aversion as _aversion;
+
+            #(#all_hops)*
+        };
+    };
+    // proc_macro2::TokenStream -> proc_macro::TokenStream
+    expanded.into()
+}
+
+/// Chain DowngradeTo implementations to skip directly to an older version.
+///
+/// If there is a FooV1..FooV4, and there is a DowngradeTo for each N+1 to N,
+/// generate the code for `DowngradeTo<FooV1> for FooV4`.
+///
+fn quote_downgrade_hop(name_info: &NameInfo, lo: u16) -> proc_macro2::TokenStream {
+    let path = name_info.upgrade_path(lo);
+    if path.len() < 3 {
+        // The user should already have provided DowngradeTo<___N> for ___M
+        return quote! {};
+    }
+
+    // Create a chain of downgrades, starting with `self`.
+    let downgrade_chain = path
+        .windows(2)
+        .rev()
+        .map(|pair| {
+            let tmp_ii = tmp_ident(pair[0]);
+            let ident_ii = name_info.version_ident(pair[0]);
+            let ident_jj = name_info.version_ident(pair[1]);
+            let src = if pair[1] == name_info.struct_version {
+                quote! { self }
+            } else {
+                let tmp_jj = tmp_ident(pair[1]);
+                quote! { &#tmp_jj }
+            };
+            quote! {
+                let #tmp_ii = <#ident_jj as _aversion::DowngradeTo<#ident_ii>>::downgrade_to(#src);
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let hi_ident = name_info.version_ident(name_info.struct_version);
+    let lo_ident = name_info.version_ident(lo);
+    let lo_tmp = tmp_ident(lo);
+
+    quote! {
+        impl _aversion::DowngradeTo<#lo_ident> for #hi_ident {
+            fn downgrade_to(&self) -> #lo_ident {
+                #(#downgrade_chain)*
+                #lo_tmp
+            }
+        }
+    }
+}
+
 /// Derive the `GroupDeserialize` trait on a struct.
 ///
 /// This macro expects an enum as input, where each variant contains exactly
 /// one field: a type that implements `Versioned + MessageId`.
 ///
-#[proc_macro_derive(GroupDeserialize)]
+/// Instead of implementing `MessageId` separately, the message id may be
+/// specified on the enum variant, e.g. `#[aversion(id = 123)] Foo(Foo)`.
+/// This macro will then generate the `MessageId` implementation.
+///
+/// By default, unknown message ids and versions are reported as errors.
+/// To tolerate messages from newer writers, either add a variant
+/// `#[aversion(unknown)] Unknown(UnknownMessage)` that will receive the
+/// raw bytes of any unknown message, or add `#[aversion(skip_unknown)]` to
+/// the enum to skip over unknown messages entirely.
+///
+#[proc_macro_derive(GroupDeserialize, attributes(aversion))]
 pub fn derive_group_deserialize(input: TokenStream) -> TokenStream {
     // parse the input into a DeriveInput syntax tree
     let input = parse_macro_input!(input as DeriveInput);
@@ -254,23 +737,129 @@ pub fn derive_group_deserialize(input: TokenStream) -> TokenStream {
     // The original generic parameters from the input struct
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
-    let source_struct = input.data;
-    let variants = if let syn::Data::Enum(syn::DataEnum { variants, .. }) = source_struct {
-        variants
+    let group_variants = match GroupVariant::from_input(&input) {
+        Ok(gv) => gv,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let skip_unknown = match AversionAttrs::from_attributes(&input.attrs, AttrTarget::Group) {
+        Ok(attrs) => attrs.skip_unknown,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let (unknown_variants, message_variants): (Vec<_>, Vec<_>) =
+        group_variants.into_iter().partition(|gv| gv.unknown);
+    if let Some(extra) = unknown_variants.get(1) {
+        return syn::Error::new(
+            extra.name.span(),
+            "only one variant may be marked `unknown`",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let unknown_variant = unknown_variants.first();
+    if let (true, Some(unknown)) = (skip_unknown, unknown_variant) {
+        return syn::Error::new(
+            unknown.name.span(),
+            "`skip_unknown` and `unknown` cannot be used together",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let read_body = quote_group_read_body(
+        enum_name,
+        &message_variants,
+        unknown_variant,
+        skip_unknown,
+        false,
+        false,
+    );
+
+    // The default `read_next` can't tell when a skipped message was the
+    // last one, so groups that skip unknown messages need their own.
+    let read_next = if skip_unknown {
+        let read_body = quote_group_read_body(
+            enum_name,
+            &message_variants,
+            unknown_variant,
+            skip_unknown,
+            true,
+            false,
+        );
+        quote! {
+            fn read_next<Src>(src: &mut Src) -> ::core::result::Result<::core::option::Option<Self>, Src::Error>
+            where
+                Src: _aversion::group::DataSource,
+            {
+                use _aversion::{MessageId, group::{GroupHeader, UpgradeLatest}};
+
+                #read_body
+            }
+        }
     } else {
-        panic!("couldn't find enum variants");
+        quote! {}
+    };
+
+    // Generate the async equivalents. They're only kept if the `aversion`
+    // crate's async feature is enabled.
+    let read_message_async = {
+        let read_body = quote_group_read_body(
+            enum_name,
+            &message_variants,
+            unknown_variant,
+            skip_unknown,
+            false,
+            true,
+        );
+        let read_next_async = if skip_unknown {
+            let read_body = quote_group_read_body(
+                enum_name,
+                &message_variants,
+                unknown_variant,
+                skip_unknown,
+                true,
+                true,
+            );
+            quote! {
+                async fn read_next_async<Src>(src: &mut Src) -> ::core::result::Result<::core::option::Option<Self>, Src::Error>
+                where
+                    Src: _aversion::group::AsyncDataSource,
+                {
+                    use _aversion::{MessageId, group::{AsyncUpgradeLatest, GroupHeader, UpgradeLatest}};
+
+                    #read_body
+                }
+            }
+        } else {
+            quote! {}
+        };
+        quote! {
+            _aversion::__async_items! {
+                #[automatically_derived]
+                impl #impl_generics _aversion::group::AsyncGroupDeserialize
+                for #enum_name #ty_generics #where_clause {
+                    async fn read_message_async<Src>(src: &mut Src) -> ::core::result::Result<Self, Src::Error>
+                    where
+                        Src: _aversion::group::AsyncDataSource,
+                    {
+                        use _aversion::{MessageId, group::{AsyncUpgradeLatest, GroupHeader, UpgradeLatest}};
+
+                        #read_body
+                    }
+
+                    #read_next_async
+                }
+            }
+        }
     };
 
-    let match_arms = variants
+    let id_impls = message_variants
         .iter()
-        .map(|v| {
-            // Extract basic data about this variant
-            let gv = GroupVariant::from_enum_variant(v);
-            // Write the GroupDeserialize match arm for this variant
-            gv.to_match_arm(enum_name)
-        })
+        .map(GroupVariant::to_id_impl)
         .collect::<Vec<_>>();
 
+    let id_checks = quote_unique_id_checks(&message_variants);
+
     let expanded = quote! {
         #[doc(hidden)]
         #[allow(
@@ -287,21 +876,186 @@ pub fn derive_group_deserialize(input: TokenStream) -> TokenStream {
             #[automatically_derived]
             impl #impl_generics _aversion::GroupDeserialize
             for #enum_name #ty_generics #where_clause {
-                fn read_message<Src>(src: &mut Src) -> ::std::result::Result<Self, Src::Error>
+                fn read_message<Src>(src: &mut Src) -> ::core::result::Result<Self, Src::Error>
                 where
                     Src: _aversion::group::DataSource,
                 {
                     use _aversion::{MessageId, group::{GroupHeader, UpgradeLatest}};
 
-                    let header = src.read_header()?;
-                    match header.msg_id() {
+                    #read_body
+                }
+
+                #read_next
+            }
+
+            #read_message_async
+
+            #(#id_impls)*
+
+            #id_checks
+        };
+    };
+
+    // proc_macro2::TokenStream -> proc_macro::TokenStream
+    expanded.into()
+}
+
+/// Generate the body of `GroupDeserialize::read_message`.
+///
+/// If `until_eof` is set, generate the body of `read_next` instead, which
+/// checks for the end of the data before reading each header.
+/// If `is_async` is set, generate the async version.
+///
+fn quote_group_read_body(
+    enum_name: &Ident,
+    message_variants: &[GroupVariant],
+    unknown_variant: Option<&GroupVariant>,
+    skip_unknown: bool,
+    until_eof: bool,
+    is_async: bool,
+) -> proc_macro2::TokenStream {
+    let await_ = quote_await(is_async);
+
+    // If unknown messages can be handled, then messages with unknown
+    // versions should be handled the same way.
+    let forward_compatible = skip_unknown || unknown_variant.is_some();
+
+    // Write the GroupDeserialize match arm for each variant
+    let match_arms = message_variants
+        .iter()
+        .map(|gv| gv.to_match_arm(enum_name, forward_compatible, is_async))
+        .collect::<Vec<_>>();
+
+    // Decide what to do with unknown messages.
+    let unknown_arm = if let Some(unknown) = unknown_variant {
+        let enum_variant = &unknown.name;
+        quote! {
+            let raw_bytes = src.read_raw_message(&header)#await_?;
+            Ok(#enum_name::#enum_variant(_aversion::group::UnknownMessage {
+                msg_id: header.msg_id(),
+                msg_ver: header.msg_ver(),
+                raw_bytes,
+            }))
+        }
+    } else if skip_unknown {
+        quote! {
+            src.skip_message(&header)#await_?;
+            continue;
+        }
+    } else {
+        quote! {
+            Err(src.unknown_message(header.msg_id()))
+        }
+    };
+
+    let read_one_message = quote! {
+        let header = src.read_header()#await_?;
+        match header.msg_id() {
+            #(#match_arms)*
+            _ => {
+                #unknown_arm
+            }
+        }
+    };
+
+    let read_one_message = if until_eof {
+        quote! {
+            if src.at_eof()#await_? {
+                return Ok(None);
+            }
+            let message = { #read_one_message };
+            message.map(Some)
+        }
+    } else {
+        read_one_message
+    };
+
+    // If unknown messages are skipped, keep reading until a known message arrives.
+    if skip_unknown {
+        quote! {
+            loop {
+                break { #read_one_message };
+            }
+        }
+    } else {
+        read_one_message
+    }
+}
+
+/// Derive the `GroupSerialize` trait on a struct.
+///
+/// This macro expects an enum as input, where each variant contains exactly
+/// one field: a type that implements `Serialize + Versioned`, whose base
+/// type implements `MessageId`.
+///
+/// A message id given with `#[aversion(id = ...)]` doesn't implement
+/// `MessageId` (that's done by `#[derive(GroupDeserialize)]`), but it's
+/// checked against the `MessageId` impl at compile time.
+///
+#[proc_macro_derive(GroupSerialize, attributes(aversion))]
+pub fn derive_group_serialize(input: TokenStream) -> TokenStream {
+    // parse the input into a DeriveInput syntax tree
+    let input = parse_macro_input!(input as DeriveInput);
+    let enum_name = &input.ident;
+
+    // The original generic parameters from the input struct
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let group_variants = match GroupVariant::from_input(&input) {
+        Ok(gv) => gv,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    // The enum's attributes only affect deserializing, but check them anyway.
+    if let Err(e) = AversionAttrs::from_attributes(&input.attrs, AttrTarget::Group) {
+        return e.to_compile_error().into();
+    }
+    if let Some(unknown) = group_variants.iter().find(|gv| gv.unknown) {
+        return syn::Error::new(
+            unknown.name.span(),
+            "GroupSerialize can't be derived for a group with an `unknown` variant",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    // Write the GroupSerialize match arm for each variant
+    let match_arms = group_variants
+        .iter()
+        .map(|gv| gv.to_write_arm(enum_name))
+        .collect::<Vec<_>>();
+
+    let id_checks = group_variants
+        .iter()
+        .map(GroupVariant::to_id_check)
+        .collect::<Vec<_>>();
+
+    let expanded = quote! {
+        #[doc(hidden)]
+        #[allow(
+            non_upper_case_globals,
+            unused_attributes,
+            unused_qualifications,
+            non_camel_case_types,
+            non_snake_case
+        )]
+        const _: () = {
+            #[allow(rust_2018_idioms, clippy::useless_attribute)]
+            extern crate aversion as _aversion;
+
+            #[automatically_derived]
+            impl #impl_generics _aversion::GroupSerialize
+            for #enum_name #ty_generics #where_clause {
+                fn write_message<Sink>(&self, sink: &mut Sink) -> ::core::result::Result<(), Sink::Error>
+                where
+                    Sink: _aversion::group::DataSink,
+                {
+                    match self {
                         #(#match_arms)*
-                        _ => {
-                            Err(src.unknown_message(header.msg_id()))
-                        }
                     }
                 }
             }
+
+            #(#id_checks)*
         };
     };
 
@@ -309,44 +1063,167 @@ pub fn derive_group_deserialize(input: TokenStream) -> TokenStream {
     expanded.into()
 }
 
+/// Generate compile-time assertions that each variant has a unique message id.
+///
+/// If two variants share a message id, one of them could never be
+/// deserialized, so this is reported as a compile error.
+///
+fn quote_unique_id_checks(variants: &[GroupVariant]) -> proc_macro2::TokenStream {
+    let mut checks = Vec::new();
+    for (ii, a) in variants.iter().enumerate() {
+        for b in &variants[ii + 1..] {
+            let a_target = &a.target;
+            let b_target = &b.target;
+            let message = format!(
+                "duplicate message id: variants `{}` and `{}` have the same MSG_ID",
+                a.name, b.name
+            );
+            checks.push(quote! {
+                assert!(
+                    <#a_target as _aversion::MessageId>::MSG_ID
+                        != <#b_target as _aversion::MessageId>::MSG_ID,
+                    #message
+                );
+            });
+        }
+    }
+
+    quote! {
+        const _: () = {
+            #(#checks)*
+        };
+    }
+}
+
 #[derive(Debug)]
 struct GroupVariant {
     name: Ident,
     target: Path,
+    /// The message id, if specified with `#[aversion(id = ...)]`.
+    msg_id: Option<u16>,
+    /// This variant holds unknown messages, i.e. `#[aversion(unknown)]`.
+    unknown: bool,
 }
 
 impl GroupVariant {
-    fn from_enum_variant(variant: &Variant) -> GroupVariant {
+    /// Extract basic data about each variant of an enum.
+    fn from_input(input: &DeriveInput) -> syn::Result<Vec<GroupVariant>> {
+        match &input.data {
+            syn::Data::Enum(syn::DataEnum { variants, .. }) => {
+                variants.iter().map(Self::from_enum_variant).collect()
+            }
+            _ => Err(syn::Error::new(
+                input.ident.span(),
+                "message groups must be enums",
+            )),
+        }
+    }
+
+    fn from_enum_variant(variant: &Variant) -> syn::Result<GroupVariant> {
         let name = variant.ident.clone();
-        let mut target_path: Option<Path> = None;
-        if let syn::Fields::Unnamed(syn::FieldsUnnamed {
-            unnamed: variant_fields,
-            ..
-        }) = &variant.fields
-        {
-            if variant_fields.len() != 1 {
-                panic!("enum must contain exactly 1 field");
+        let variant_fields = match &variant.fields {
+            syn::Fields::Unnamed(syn::FieldsUnnamed { unnamed, .. }) if unnamed.len() == 1 => {
+                unnamed
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    variant,
+                    "enum variant must contain exactly 1 unnamed field",
+                ))
             }
-            let field = variant_fields.first().unwrap();
+        };
+        let field = variant_fields.first().unwrap();
 
-            if let syn::Type::Path(syn::TypePath { path, .. }) = &field.ty {
-                target_path = Some(path.clone());
+        let target = match &field.ty {
+            syn::Type::Path(syn::TypePath { path, .. }) => path.clone(),
+            ty => {
+                return Err(syn::Error::new_spanned(
+                    ty,
+                    format!("failed to extract enum target path for {}", name),
+                ))
             }
+        };
+
+        let attrs = AversionAttrs::from_attributes(&variant.attrs, AttrTarget::Variant)?;
+
+        Ok(GroupVariant {
+            name,
+            target,
+            msg_id: attrs.id,
+            unknown: attrs.unknown,
+        })
+    }
+
+    /// Generate a `MessageId` impl, if the message id was specified on the variant.
+    fn to_id_impl(&self) -> proc_macro2::TokenStream {
+        let struct_name = &self.target;
+        match self.msg_id {
+            Some(msg_id) => quote! {
+                #[automatically_derived]
+                impl _aversion::MessageId for #struct_name {
+                    const MSG_ID: u16 = #msg_id;
+                }
+            },
+            None => quote! {},
         }
+    }
 
-        let target = target_path
-            .unwrap_or_else(|| panic!("failed to extract enum target path for {}", name));
+    /// Generate a compile-time check that the message id specified on the
+    /// variant (if any) matches the message's `MessageId` impl.
+    fn to_id_check(&self) -> proc_macro2::TokenStream {
+        let struct_name = &self.target;
+        match self.msg_id {
+            Some(msg_id) => {
+                let message = format!(
+                    "message id mismatch: variant `{}` specifies id {}, but MSG_ID is different",
+                    self.name, msg_id
+                );
+                quote! {
+                    const _: () = assert!(
+                        <#struct_name as _aversion::MessageId>::MSG_ID == #msg_id,
+                        #message
+                    );
+                }
+            }
+            None => quote! {},
+        }
+    }
+
+    fn to_write_arm(&self, enum_name: &Ident) -> proc_macro2::TokenStream {
+        let enum_variant = &self.name;
 
-        GroupVariant { name, target }
+        quote! {
+            #enum_name::#enum_variant(msg) => sink.write_message(msg),
+        }
     }
 
-    fn to_match_arm(&self, enum_name: &Ident) -> proc_macro2::TokenStream {
+    /// Generate the `GroupDeserialize` match arm for this variant.
+    ///
+    /// If `check_version` is set, the arm will only match versions that
+    /// are supported, so that unknown versions can be handled like
+    /// unknown messages.
+    fn to_match_arm(
+        &self,
+        enum_name: &Ident,
+        check_version: bool,
+        is_async: bool,
+    ) -> proc_macro2::TokenStream {
         let enum_variant = &self.name;
         let struct_name = &self.target;
+        let upgrade = if is_async {
+            quote! { #struct_name::upgrade_latest_async(src, header).await? }
+        } else {
+            quote! { #struct_name::upgrade_latest(src, header)? }
+        };
+        let guard = if check_version {
+            quote! { if <#struct_name as UpgradeLatest>::supports_version(header.msg_ver()) }
+        } else {
+            quote! {}
+        };
 
         quote! {
-            #struct_name::MSG_ID => {
-                let msg = #struct_name::upgrade_latest(src, header)?;
+            #struct_name::MSG_ID #guard => {
+                let msg = #upgrade;
                 Ok(#enum_name::#enum_variant(msg))
             }
         }
@@ -395,8 +1272,7 @@ struct MessageIdList {
 #[derive(Debug)]
 struct MessageIdValue {
     name: Path,
-    // FIXME: validate this fits in u16.
-    msg_id: LitInt,
+    msg_id: u16,
 }
 
 /// Parse a single message-id value, e.g. `Foo: 123`
@@ -407,6 +1283,7 @@ impl Parse for MessageIdValue {
         let name: Path = input.parse()?;
         input.parse::<Token![:]>()?;
         let msg_id: LitInt = input.parse()?;
+        let msg_id = parse_message_id(&msg_id)?;
         Ok(MessageIdValue { name, msg_id })
     }
 }
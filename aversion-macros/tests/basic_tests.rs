@@ -52,3 +52,34 @@ fn basic() {
         }
     )
 }
+
+#[test]
+fn attribute() {
+    pub type Thing = ThingCurrent;
+
+    #[derive(Versioned)]
+    #[aversion(base = Thing, version = 1)]
+    pub struct LegacyThing {
+        _name: String,
+    }
+
+    #[derive(Versioned)]
+    #[aversion(base = Thing, version = 2)]
+    pub struct ThingCurrent {
+        _name: String,
+        _size: u64,
+    }
+
+    // A name that would confuse the naming convention.
+    #[derive(Versioned)]
+    #[aversion(base = Server, version = 7)]
+    pub struct ServerVersion;
+
+    type Server = ServerVersion;
+
+    assert_eq!(LegacyThing::VER, 1);
+    assert_eq!(ThingCurrent::VER, 2);
+    assert_eq!(ServerVersion::VER, 7);
+    assert_eq!(<LegacyThing as Versioned>::Base::VER, 2);
+    assert_eq!(<ServerVersion as Versioned>::Base::VER, 7);
+}
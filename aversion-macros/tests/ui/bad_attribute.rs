@@ -0,0 +1,29 @@
+use aversion::Versioned;
+
+type Foo = FooCurrent;
+
+#[derive(Versioned)]
+#[aversion(base = Foo)]
+struct FooCurrent {
+    x: u32,
+}
+
+#[derive(Versioned)]
+#[aversion(base = Foo, version = 0)]
+struct FooZero {
+    x: u32,
+}
+
+#[derive(Versioned)]
+#[aversion(base = Foo, verson = 2)]
+struct FooTypo {
+    x: u32,
+}
+
+#[derive(Versioned)]
+#[aversion(base = Foo, version = 70000)]
+struct FooHuge {
+    x: u32,
+}
+
+fn main() {}
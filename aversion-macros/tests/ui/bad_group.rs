@@ -0,0 +1,43 @@
+use aversion::GroupDeserialize;
+
+#[derive(GroupDeserialize)]
+struct NotAnEnum {
+    x: u32,
+}
+
+#[derive(GroupDeserialize)]
+enum TwoFields {
+    Foo(u32, u32),
+}
+
+#[derive(GroupDeserialize)]
+enum NamedFields {
+    Foo { x: u32 },
+}
+
+#[derive(GroupDeserialize)]
+enum NotAPath {
+    Foo(&'static str),
+}
+
+#[derive(GroupDeserialize)]
+enum TwoUnknowns {
+    #[aversion(unknown)]
+    Unknown1(aversion::group::UnknownMessage),
+    #[aversion(unknown)]
+    Unknown2(aversion::group::UnknownMessage),
+}
+
+#[derive(GroupDeserialize)]
+#[aversion(skip_unknown)]
+enum SkipAndUnknown {
+    #[aversion(unknown)]
+    Unknown(aversion::group::UnknownMessage),
+}
+
+#[derive(GroupDeserialize, aversion::GroupSerialize)]
+enum SerializeUnknown {
+    #[aversion(unknown)]
+    Unknown(aversion::group::UnknownMessage),
+}
+fn main() {}
@@ -0,0 +1,21 @@
+use aversion::{assign_message_ids, GroupDeserialize, Versioned};
+
+#[derive(Versioned)]
+struct FooV1;
+type Foo = FooV1;
+
+assign_message_ids! {
+    Foo: 65536,
+}
+
+#[derive(Versioned)]
+struct BarV1;
+type Bar = BarV1;
+
+#[derive(GroupDeserialize)]
+enum MyGroup {
+    #[aversion(id = 70000)]
+    Bar(Bar),
+}
+
+fn main() {}
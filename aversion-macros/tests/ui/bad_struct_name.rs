@@ -0,0 +1,13 @@
+use aversion::Versioned;
+
+#[derive(Versioned)]
+struct Unversioned {
+    x: u32,
+}
+
+#[derive(Versioned)]
+struct FooVx {
+    x: u32,
+}
+
+fn main() {}
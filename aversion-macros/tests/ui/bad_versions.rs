@@ -0,0 +1,15 @@
+use aversion::{UpgradeLatest, Versioned};
+
+#[derive(Versioned, UpgradeLatest)]
+#[aversion(versions(1, 4))]
+struct FooV3 {
+    x: u32,
+}
+
+#[derive(Versioned, UpgradeLatest)]
+#[aversion(versions(1, 2), min_version = 2)]
+struct BarV3 {
+    x: u32,
+}
+
+fn main() {}
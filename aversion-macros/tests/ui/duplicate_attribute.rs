@@ -0,0 +1,37 @@
+use aversion::{GroupDeserialize, UpgradeLatest, Versioned};
+use serde::Deserialize;
+
+type Foo = FooCurrent;
+
+#[derive(Versioned)]
+#[aversion(base = Foo, version = 2, version = 3)]
+struct FooCurrent {
+    x: u32,
+}
+
+#[derive(Versioned)]
+#[aversion(base = Foo, version = 2)]
+#[aversion(base = Foo)]
+struct FooOther {
+    x: u32,
+}
+
+#[derive(Versioned, Deserialize, UpgradeLatest)]
+struct BarV1 {
+    x: u32,
+}
+type Bar = BarV1;
+
+#[derive(GroupDeserialize)]
+enum DuplicateId {
+    #[aversion(id = 1, id = 2)]
+    Bar(Bar),
+}
+
+#[derive(GroupDeserialize)]
+#[aversion(skip_unknown, skip_unknown)]
+enum DuplicateSkip {
+    Bar(Bar),
+}
+
+fn main() {}
@@ -0,0 +1,23 @@
+use aversion::{assign_message_ids, GroupDeserialize, UpgradeLatest, Versioned};
+use serde::Deserialize;
+
+#[derive(Versioned, UpgradeLatest, Deserialize)]
+struct FooV1;
+type Foo = FooV1;
+
+#[derive(Versioned, UpgradeLatest, Deserialize)]
+struct BarV1;
+type Bar = BarV1;
+
+assign_message_ids! {
+    Foo: 100,
+    Bar: 100,
+}
+
+#[derive(GroupDeserialize)]
+enum MyGroup {
+    Foo(Foo),
+    Bar(Bar),
+}
+
+fn main() {}
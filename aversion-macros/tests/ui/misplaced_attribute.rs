@@ -0,0 +1,58 @@
+use aversion::{GroupDeserialize, GroupSerialize, UpgradeLatest, Versioned};
+use serde::{Deserialize, Serialize};
+
+#[derive(Versioned)]
+#[aversion(id = 5, skip_unknown, unknown)]
+struct FooV1 {
+    x: u32,
+}
+
+#[derive(Versioned)]
+#[aversion(skip_unknown)]
+struct BarV1 {
+    x: u32,
+}
+
+#[derive(Versioned)]
+#[aversion(unknown)]
+struct BazV1 {
+    x: u32,
+}
+
+#[derive(Versioned, Serialize, Deserialize, UpgradeLatest)]
+struct QuxV1 {
+    x: u32,
+}
+type Qux = QuxV1;
+
+#[derive(GroupDeserialize)]
+enum BaseOnVariant {
+    #[aversion(base = Qux)]
+    Qux(Qux),
+}
+
+#[derive(GroupDeserialize)]
+enum VersionOnVariant {
+    #[aversion(version = 2)]
+    Qux(Qux),
+}
+
+#[derive(GroupDeserialize)]
+enum VersionsOnVariant {
+    #[aversion(versions(1, 2))]
+    Qux(Qux),
+}
+
+#[derive(GroupSerialize)]
+enum MinVersionOnVariant {
+    #[aversion(min_version = 1)]
+    Qux(Qux),
+}
+
+#[derive(GroupDeserialize)]
+#[aversion(id = 7)]
+enum IdOnGroup {
+    Qux(Qux),
+}
+
+fn main() {}
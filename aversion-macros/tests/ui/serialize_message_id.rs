@@ -0,0 +1,19 @@
+use aversion::{assign_message_ids, GroupSerialize, Versioned};
+use serde::Serialize;
+
+#[derive(Versioned, Serialize)]
+struct FooV1;
+type Foo = FooV1;
+
+assign_message_ids! {
+    Foo: 100,
+}
+
+// GroupSerialize checks the message id, but doesn't implement MessageId.
+#[derive(GroupSerialize)]
+enum MyGroup {
+    #[aversion(id = 101)]
+    Foo(Foo),
+}
+
+fn main() {}
@@ -0,0 +1,55 @@
+use alloc::boxed::Box;
+use thiserror::Error;
+
+#[cfg(not(feature = "std"))]
+use core::error::Error as StdError;
+#[cfg(feature = "std")]
+use std::error::Error as StdError;
+
+/// Errors detected while reading a message group.
+///
+/// These are returned by the default implementations of the
+/// [`DataSource`] error functions, such as [`unknown_message`]. Because
+/// [`DataSource::Error`] must implement `From<aversion::Error>`, a data
+/// source doesn't need to implement those functions, unless it wants to
+/// construct its own error values.
+///
+/// [`DataSource`]: crate::group::DataSource
+/// [`DataSource::Error`]: crate::group::DataSource::Error
+/// [`unknown_message`]: crate::group::DataSource::unknown_message
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// A message with an unknown message id was received.
+    #[error("Unknown message id {msg_id}")]
+    UnknownMessage {
+        /// The message id.
+        msg_id: u16,
+    },
+    /// An unknown version of a known message was received.
+    #[error("Unknown version {ver} of {type_name}")]
+    UnknownVersion {
+        /// The name of the message type.
+        type_name: &'static str,
+        /// The message version.
+        ver: u16,
+    },
+    /// A specific message type was expected, but a different message was received.
+    #[error("Expected {expected}, got message id {msg_id}")]
+    UnexpectedMessage {
+        /// The name of the expected message type.
+        expected: &'static str,
+        /// The message id that was received.
+        msg_id: u16,
+    },
+    /// A message was read, but upgrading it to the latest version failed.
+    #[error("Failed to upgrade version {ver} of {type_name}")]
+    MigrationFailed {
+        /// The name of the message type.
+        type_name: &'static str,
+        /// The message version.
+        ver: u16,
+        /// The error returned by the upgrade.
+        source: Box<dyn StdError + Send + Sync>,
+    },
+}
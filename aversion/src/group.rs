@@ -4,13 +4,30 @@
 //! For example, a file format or a network protocol may form a group.
 //!
 
-use crate::{MessageId, Versioned};
+use crate::{DowngradeTo, MessageId, Versioned};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::any::type_name;
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use std::any::type_name;
 
-/// A data structure that contains a message-id and version fields.
+#[cfg(not(feature = "std"))]
+use core::error::Error;
+#[cfg(feature = "std")]
+use std::error::Error;
+
+#[cfg(feature = "async")]
+mod asynch;
 
+#[cfg(feature = "async")]
+#[doc(inline)]
+pub use asynch::{
+    AsyncDataSink, AsyncDataSource, AsyncDataSourceExt, AsyncGroupDeserialize, AsyncUpgradeLatest,
+};
+
+/// A data structure that contains a message-id and version fields.
 pub trait GroupHeader {
     /// Retrieve the message id.
     fn msg_id(&self) -> u16;
@@ -20,17 +37,24 @@ pub trait GroupHeader {
 
 /// A trait for deserializing any version of a [`Versioned`] data structure.
 ///
-/// This trait will normally be derived using `#[derive(Versioned)]`.
+/// This trait will normally be derived using `#[derive(UpgradeLatest)]`.
+/// The derive macro assumes that every version `1..=latest` exists, unless
+/// a list of versions is specified with `#[aversion(versions(...))]` or
+/// `#[aversion(min_version = ...)]`.
 ///
-// How will the macro know which versions exist?
-// a) Macro will assume that every version [1..latest] exists
-//    - and maybe there's a macro to generate stubs for missing versions?
-// b) User needs to specify a range or list of versions
 pub trait UpgradeLatest: DeserializeOwned + Versioned {
     /// Deserialize version `ver` of the target struct, then upgrade it to the latest version.
     fn upgrade_latest<Src>(src: &mut Src, header: Src::Header) -> Result<Self, Src::Error>
     where
         Src: DataSource;
+
+    /// Returns `true` if version `ver` can be upgraded to the latest version.
+    ///
+    /// The default implementation assumes that every version from 1 to
+    /// the latest version is supported.
+    fn supports_version(ver: u16) -> bool {
+        (1..=Self::VER).contains(&ver)
+    }
 }
 
 /// `DataSource` allows user-defined IO, deserialization, and
@@ -41,9 +65,13 @@ pub trait DataSource {
     ///
     /// This error type will be returned from [`read_header`][Self::read_header]
     /// and [`read_message`][Self::read_message].
-    /// It's probably a good idea for it to be able to represent IO errors,
-    /// deserialization errors, and "unknown message" errors.
-    type Error;
+    /// It's probably a good idea for it to be able to represent IO errors
+    /// and deserialization errors. It must be possible to convert an
+    /// [`aversion::Error`] into this type; that is used to report unknown
+    /// messages and other problems detected while reading a message group.
+    ///
+    /// [`aversion::Error`]: crate::Error
+    type Error: From<crate::Error>;
     /// A user-defined header struct.
     ///
     /// The `Header` is a way of communicating what kind of message is being
@@ -66,6 +94,52 @@ pub trait DataSource {
     where
         T: DeserializeOwned;
 
+    /// Read the raw bytes of a message, without deserializing it.
+    ///
+    /// This is a user-defined function that will read the message that
+    /// follows `header`, leaving the data source positioned at the next
+    /// header. It is used by [`GroupDeserialize::read_message`] to capture
+    /// unknown messages.
+    ///
+    /// The default implementation returns an
+    /// [`unknown_message`][Self::unknown_message] error, because not all
+    /// header formats specify the length of the message.
+    ///
+    fn read_raw_message(&mut self, header: &Self::Header) -> Result<Vec<u8>, Self::Error> {
+        Err(self.unknown_message(header.msg_id()))
+    }
+
+    /// Skip over a message, without deserializing it.
+    ///
+    /// This is a user-defined function that will discard the message that
+    /// follows `header`, leaving the data source positioned at the next
+    /// header. It is used by [`GroupDeserialize::read_message`] to skip
+    /// unknown messages.
+    ///
+    /// The default implementation calls [`read_raw_message`][Self::read_raw_message]
+    /// and discards the result.
+    ///
+    fn skip_message(&mut self, header: &Self::Header) -> Result<(), Self::Error> {
+        self.read_raw_message(header).map(drop)
+    }
+
+    /// Check whether the data source has no more messages.
+    ///
+    /// This is a user-defined function that returns `true` if the data
+    /// source ended cleanly, at the point where the next header would
+    /// start. Running out of data partway through a header or message
+    /// is not a clean end, and should be reported when that header or
+    /// message is read.
+    ///
+    /// This is used by [`GroupDeserialize::read_next`] and the
+    /// [`messages`][DataSourceExt::messages] iterator.
+    ///
+    /// The default implementation always returns `false`.
+    ///
+    fn at_eof(&mut self) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
     /// An unknown message id was received.
     ///
     /// This is a user-defined function that constructs an error value.
@@ -73,8 +147,12 @@ pub trait DataSource {
     /// when an unknown message is received (a message with an unknown
     /// message id).
     ///
+    /// The default implementation returns [`aversion::Error::UnknownMessage`].
+    ///
+    /// [`aversion::Error::UnknownMessage`]: crate::Error::UnknownMessage
+    ///
     fn unknown_message(&self, msg_id: u16) -> Self::Error {
-        panic!("unknown message id {}", msg_id);
+        crate::Error::UnknownMessage { msg_id }.into()
     }
 
     /// An unknown version of a known message was received.
@@ -84,8 +162,40 @@ pub trait DataSource {
     /// when a known message id is received, but with a message version that
     /// is unknown.
     ///
+    /// The default implementation returns [`aversion::Error::UnknownVersion`].
+    ///
+    /// [`aversion::Error::UnknownVersion`]: crate::Error::UnknownVersion
+    ///
     fn unknown_version<T>(&self, ver: u16) -> Self::Error {
-        panic!("unknown version {} for {}", ver, type_name::<T>());
+        crate::Error::UnknownVersion {
+            type_name: type_name::<T>(),
+            ver,
+        }
+        .into()
+    }
+
+    /// A fallible upgrade of a known message failed.
+    ///
+    /// This is a user-defined function that constructs an error value.
+    /// This function will be called by [`UpgradeLatest::upgrade_latest`]
+    /// when version `ver` of message `T` was read, but a [`TryFromVersion`]
+    /// conversion to a newer version returned an error.
+    ///
+    /// The default implementation returns [`aversion::Error::MigrationFailed`].
+    ///
+    /// [`TryFromVersion`]: crate::TryFromVersion
+    /// [`aversion::Error::MigrationFailed`]: crate::Error::MigrationFailed
+    ///
+    fn migration_failed<T, E>(&self, ver: u16, error: E) -> Self::Error
+    where
+        E: Error + Send + Sync + 'static,
+    {
+        crate::Error::MigrationFailed {
+            type_name: type_name::<T>(),
+            ver,
+            source: Box::new(error),
+        }
+        .into()
     }
 
     /// Expected a specific message type, but got a different message id.
@@ -95,15 +205,32 @@ pub trait DataSource {
     /// when a different message id is received from the message that was
     /// specified.
     ///
+    /// The default implementation returns [`aversion::Error::UnexpectedMessage`].
+    ///
+    /// [`aversion::Error::UnexpectedMessage`]: crate::Error::UnexpectedMessage
+    ///
     fn unexpected_message<T>(&self, msg_id: u16) -> Self::Error {
-        panic!(
-            "unexpected message id {} (expected {})",
+        crate::Error::UnexpectedMessage {
+            expected: type_name::<T>(),
             msg_id,
-            type_name::<T>()
-        );
+        }
+        .into()
     }
 }
 
+/// A [`DataSource`] that can read the next header without consuming it.
+///
+/// This is useful for deciding how to read the next message (or which
+/// group enum to read it with) before actually reading it.
+pub trait PeekHeader: DataSource {
+    /// Read the next header from the data source, without consuming it.
+    ///
+    /// The header will be kept in an internal buffer, and the next
+    /// call to [`read_header`][DataSource::read_header] will return
+    /// the same header.
+    fn peek_header(&mut self) -> Result<&Self::Header, Self::Error>;
+}
+
 /// Useful functions for `DataSource`.
 ///
 /// There is a blanket implementation of this trait, so that any
@@ -118,6 +245,25 @@ pub trait DataSourceExt: DataSource {
     fn expect_message<T>(&mut self) -> Result<T, Self::Error>
     where
         T: MessageId + UpgradeLatest;
+
+    /// Check whether the next message is of type `T`.
+    ///
+    /// This will peek at the next header, without consuming it, and
+    /// compare its message id to the message id of `T`.
+    fn next_is<T>(&mut self) -> Result<bool, Self::Error>
+    where
+        T: MessageId,
+        Self: PeekHeader;
+
+    /// Iterate over the messages in the `DataSource`.
+    ///
+    /// Each message will be read using [`GroupDeserialize::read_next`].
+    /// The iterator ends when the data source ends cleanly (see
+    /// [`at_eof`][DataSource::at_eof]), or after the first error.
+    fn messages<G>(&mut self) -> Messages<'_, Self, G>
+    where
+        G: GroupDeserialize,
+        Self: Sized;
 }
 
 impl<Src> DataSourceExt for Src
@@ -137,9 +283,129 @@ where
             Err(self.unexpected_message::<T>(header.msg_id()))
         }
     }
+
+    fn next_is<T>(&mut self) -> Result<bool, Src::Error>
+    where
+        T: MessageId,
+        Src: PeekHeader,
+    {
+        let header = self.peek_header()?;
+        Ok(header.msg_id() == T::MSG_ID)
+    }
+
+    fn messages<G>(&mut self) -> Messages<'_, Src, G>
+    where
+        G: GroupDeserialize,
+    {
+        Messages {
+            src: self,
+            done: false,
+            _group: PhantomData,
+        }
+    }
+}
+
+/// An iterator over the messages in a [`DataSource`].
+///
+/// This is returned by [`DataSourceExt::messages`].
+pub struct Messages<'a, Src, G> {
+    src: &'a mut Src,
+    done: bool,
+    _group: PhantomData<fn() -> G>,
+}
+
+impl<Src, G> Iterator for Messages<'_, Src, G>
+where
+    Src: DataSource,
+    G: GroupDeserialize,
+{
+    type Item = Result<G, Src::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match G::read_next(self.src) {
+            Ok(Some(msg)) => Some(Ok(msg)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                // After an error, the position in the data source is unknown.
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<Src, G> FusedIterator for Messages<'_, Src, G>
+where
+    Src: DataSource,
+    G: GroupDeserialize,
+{
+}
+
+/// A message that was not recognized by [`GroupDeserialize`].
+///
+/// A message group enum may contain a variant that holds an `UnknownMessage`,
+/// marked with `#[aversion(unknown)]`. Any message with an unknown message id,
+/// or an unknown version of a known message, will be returned in that variant
+/// rather than being reported as an error. This allows readers built against
+/// an older protocol to continue reading when a newer writer sends messages
+/// they don't understand.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownMessage {
+    /// The message id.
+    pub msg_id: u16,
+    /// The message version.
+    pub msg_ver: u16,
+    /// The raw (serialized) message bytes.
+    pub raw_bytes: Vec<u8>,
+}
+
+impl GroupHeader for UnknownMessage {
+    fn msg_id(&self) -> u16 {
+        self.msg_id
+    }
+
+    fn msg_ver(&self) -> u16 {
+        self.msg_ver
+    }
 }
 
 /// A derived trait that can deserialize any message from a group.
+///
+/// This trait will normally be derived using `#[derive(GroupDeserialize)]`
+/// on an enum. Each variant of the enum must contain a single message type
+/// that implements [`MessageId`]. The message id can be specified on the
+/// variant, instead of implementing `MessageId` separately:
+/// ```
+/// # use aversion::{GroupDeserialize, UpgradeLatest, Versioned};
+/// # use serde::Deserialize;
+/// # #[derive(Deserialize, UpgradeLatest, Versioned)]
+/// # struct FooV1;
+/// # type Foo = FooV1;
+/// # #[derive(Deserialize, UpgradeLatest, Versioned)]
+/// # struct BarV1;
+/// # type Bar = BarV1;
+/// #[derive(GroupDeserialize)]
+/// enum MyProtocol {
+///     #[aversion(id = 100)]
+///     Foo(Foo),
+///     #[aversion(id = 101)]
+///     Bar(Bar),
+/// }
+/// ```
+///
+/// By default, a message with an unknown message id or version is an error.
+/// Groups that need to tolerate newer writers can add a variant marked
+/// `#[aversion(unknown)]` holding an [`UnknownMessage`], or add the
+/// `#[aversion(skip_unknown)]` attribute to the enum to skip over unknown
+/// messages. Either option requires a [`DataSource`] that implements
+/// [`read_raw_message`][DataSource::read_raw_message].
 pub trait GroupDeserialize: Sized {
     /// Read the next message from the `DataSource`.
     ///
@@ -150,6 +416,43 @@ pub trait GroupDeserialize: Sized {
     fn read_message<Src>(src: &mut Src) -> Result<Self, Src::Error>
     where
         Src: DataSource;
+
+    /// Read the next message from the `DataSource`, if there is one.
+    ///
+    /// This is the same as [`read_message`][Self::read_message], except
+    /// that it returns `None` if the data source has ended cleanly
+    /// (see [`DataSource::at_eof`]).
+    fn read_next<Src>(src: &mut Src) -> Result<Option<Self>, Src::Error>
+    where
+        Src: DataSource,
+    {
+        if src.at_eof()? {
+            return Ok(None);
+        }
+        Self::read_message(src).map(Some)
+    }
+}
+
+/// A derived trait that can serialize any message from a group.
+///
+/// This trait will normally be derived using `#[derive(GroupSerialize)]`
+/// on an enum, in the same way as [`GroupDeserialize`]. Message ids may be
+/// specified on the variants with `#[aversion(id = ...)]`, but only
+/// `#[derive(GroupDeserialize)]` implements [`MessageId`] from them. If a
+/// group only derives `GroupSerialize`, the `MessageId` impls must come
+/// from elsewhere (e.g. [`assign_message_ids!`]), and the derive checks
+/// that they match the ids on the variants.
+///
+/// [`assign_message_ids!`]: crate::assign_message_ids
+pub trait GroupSerialize {
+    /// Write this message to the `DataSink`.
+    ///
+    /// This will write the message header and message for whichever
+    /// enum variant `self` contains, so that it may be read back using
+    /// [`GroupDeserialize::read_message`].
+    fn write_message<Sink>(&self, sink: &mut Sink) -> Result<(), Sink::Error>
+    where
+        Sink: DataSink;
 }
 
 /// `DataSink` allows user-defined IO, deserialization, and
@@ -169,4 +472,18 @@ pub trait DataSink {
     where
         T: Serialize + Versioned,
         T::Base: MessageId;
+
+    /// Downgrade a message to an older version, then write it to the data sink.
+    ///
+    /// This is useful when communicating with a peer that only understands
+    /// an older version of a message, e.g.
+    /// `sink.write_message_as::<FooV2>(&foo)`.
+    ///
+    fn write_message_as<U>(&mut self, msg: &impl DowngradeTo<U>) -> Result<(), Self::Error>
+    where
+        U: Serialize + Versioned,
+        U::Base: MessageId,
+    {
+        self.write_message(&msg.downgrade_to())
+    }
 }
@@ -0,0 +1,251 @@
+//! Async versions of `DataSource` and `DataSink`.
+
+use super::{GroupDeserialize, GroupHeader, UpgradeLatest};
+use crate::{MessageId, Versioned};
+use futures_util::stream::{self, Stream};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any::type_name;
+use std::error::Error;
+use std::future::{ready, Future};
+
+/// A trait for deserializing any version of a [`Versioned`] data structure
+/// from an [`AsyncDataSource`].
+///
+/// This is the async equivalent of [`UpgradeLatest`]. It is a separate
+/// trait, so that enabling the `async` feature doesn't add methods to
+/// `UpgradeLatest`. `#[derive(UpgradeLatest)]` implements both traits
+/// when the `async` feature is enabled.
+///
+pub trait AsyncUpgradeLatest: UpgradeLatest {
+    /// Deserialize version `ver` of the target struct from an [`AsyncDataSource`],
+    /// then upgrade it to the latest version.
+    fn upgrade_latest_async<Src>(
+        src: &mut Src,
+        header: Src::Header,
+    ) -> impl Future<Output = Result<Self, Src::Error>> + Send
+    where
+        Src: AsyncDataSource;
+}
+
+/// A derived trait that can deserialize any message from a group, using
+/// an [`AsyncDataSource`].
+///
+/// This is the async equivalent of [`GroupDeserialize`]. It is a separate
+/// trait, so that enabling the `async` feature doesn't add methods to
+/// `GroupDeserialize`. `#[derive(GroupDeserialize)]` implements both
+/// traits when the `async` feature is enabled; every message in the group
+/// must then implement [`AsyncUpgradeLatest`].
+///
+pub trait AsyncGroupDeserialize: GroupDeserialize {
+    /// Read the next message from an [`AsyncDataSource`].
+    ///
+    /// This is the async equivalent of [`GroupDeserialize::read_message`].
+    fn read_message_async<Src>(
+        src: &mut Src,
+    ) -> impl Future<Output = Result<Self, Src::Error>> + Send
+    where
+        Src: AsyncDataSource;
+
+    /// Read the next message from an [`AsyncDataSource`], if there is one.
+    ///
+    /// This is the async equivalent of [`GroupDeserialize::read_next`].
+    fn read_next_async<Src>(
+        src: &mut Src,
+    ) -> impl Future<Output = Result<Option<Self>, Src::Error>> + Send
+    where
+        Src: AsyncDataSource,
+    {
+        async move {
+            if src.at_eof().await? {
+                return Ok(None);
+            }
+            Self::read_message_async(src).await.map(Some)
+        }
+    }
+}
+
+/// `AsyncDataSource` allows user-defined async IO, deserialization, and
+/// error handling.
+///
+/// This is the async equivalent of [`DataSource`]; see its documentation
+/// for more details. The futures returned by this trait must be `Send`,
+/// so that they may be used in multithreaded async runtimes.
+///
+/// [`DataSource`]: super::DataSource
+///
+pub trait AsyncDataSource: Send {
+    /// A user-defined error type.
+    ///
+    /// This error type will be returned from [`read_header`][Self::read_header]
+    /// and [`read_message`][Self::read_message].
+    ///
+    /// As with [`DataSource::Error`], it must be possible to convert an
+    /// [`aversion::Error`] into this type.
+    ///
+    /// [`DataSource::Error`]: super::DataSource::Error
+    /// [`aversion::Error`]: crate::Error
+    type Error: Send + From<crate::Error>;
+    /// A user-defined header struct.
+    ///
+    /// The `Header` is a way of communicating what kind of message is being
+    /// sent, along with the message version.
+    type Header: GroupHeader + Send + Sync;
+
+    /// Read a header from the data source.
+    fn read_header(&mut self) -> impl Future<Output = Result<Self::Header, Self::Error>> + Send;
+
+    /// Read a message from the data source.
+    ///
+    /// This is a user-defined function that will deserialize a message
+    /// of type `T`.
+    fn read_message<T>(
+        &mut self,
+        header: &Self::Header,
+    ) -> impl Future<Output = Result<T, Self::Error>> + Send
+    where
+        T: DeserializeOwned;
+
+    /// Read the raw bytes of a message, without deserializing it.
+    ///
+    /// The default implementation returns an
+    /// [`unknown_message`][Self::unknown_message] error, because not all
+    /// header formats specify the length of the message.
+    ///
+    fn read_raw_message(
+        &mut self,
+        header: &Self::Header,
+    ) -> impl Future<Output = Result<Vec<u8>, Self::Error>> + Send {
+        ready(Err(self.unknown_message(header.msg_id())))
+    }
+
+    /// Skip over a message, without deserializing it.
+    ///
+    /// The default implementation calls [`read_raw_message`][Self::read_raw_message]
+    /// and discards the result.
+    ///
+    fn skip_message(
+        &mut self,
+        header: &Self::Header,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async move { self.read_raw_message(header).await.map(drop) }
+    }
+
+    /// Check whether the data source has no more messages.
+    ///
+    /// This is the async equivalent of [`DataSource::at_eof`].
+    /// The default implementation always returns `false`.
+    ///
+    /// [`DataSource::at_eof`]: super::DataSource::at_eof
+    ///
+    fn at_eof(&mut self) -> impl Future<Output = Result<bool, Self::Error>> + Send {
+        ready(Ok(false))
+    }
+
+    /// An unknown message id was received.
+    ///
+    /// This is a user-defined function that constructs an error value.
+    ///
+    /// The default implementation returns [`aversion::Error::UnknownMessage`].
+    ///
+    /// [`aversion::Error::UnknownMessage`]: crate::Error::UnknownMessage
+    ///
+    fn unknown_message(&self, msg_id: u16) -> Self::Error {
+        crate::Error::UnknownMessage { msg_id }.into()
+    }
+
+    /// An unknown version of a known message was received.
+    ///
+    /// This is a user-defined function that constructs an error value.
+    ///
+    /// The default implementation returns [`aversion::Error::UnknownVersion`].
+    ///
+    /// [`aversion::Error::UnknownVersion`]: crate::Error::UnknownVersion
+    ///
+    fn unknown_version<T>(&self, ver: u16) -> Self::Error {
+        crate::Error::UnknownVersion {
+            type_name: type_name::<T>(),
+            ver,
+        }
+        .into()
+    }
+
+    /// A fallible upgrade of a known message failed.
+    ///
+    /// This is a user-defined function that constructs an error value.
+    ///
+    /// The default implementation returns [`aversion::Error::MigrationFailed`].
+    ///
+    /// [`aversion::Error::MigrationFailed`]: crate::Error::MigrationFailed
+    ///
+    fn migration_failed<T, E>(&self, ver: u16, error: E) -> Self::Error
+    where
+        E: Error + Send + Sync + 'static,
+    {
+        crate::Error::MigrationFailed {
+            type_name: type_name::<T>(),
+            ver,
+            source: Box::new(error),
+        }
+        .into()
+    }
+}
+
+/// Useful functions for `AsyncDataSource`.
+///
+/// There is a blanket implementation of this trait, so that any
+/// [`AsyncDataSource`] type can use these functions.
+pub trait AsyncDataSourceExt: AsyncDataSource {
+    /// Return a [`Stream`] of the messages in the `AsyncDataSource`.
+    ///
+    /// Each message will be read using [`AsyncGroupDeserialize::read_next_async`].
+    /// The stream ends when the data source ends cleanly (see
+    /// [`at_eof`][AsyncDataSource::at_eof]), or after the first error.
+    ///
+    /// The stream is not `Unpin`, so it may need to be pinned (e.g. with
+    /// [`std::pin::pin!`]) before calling `next()` on it.
+    fn messages<G>(&mut self) -> impl Stream<Item = Result<G, Self::Error>> + Send + '_
+    where
+        G: AsyncGroupDeserialize + Send;
+}
+
+impl<Src> AsyncDataSourceExt for Src
+where
+    Src: AsyncDataSource,
+{
+    fn messages<G>(&mut self) -> impl Stream<Item = Result<G, Src::Error>> + Send + '_
+    where
+        G: AsyncGroupDeserialize + Send,
+    {
+        // The state is `None` after an error, because the position in
+        // the data source is unknown.
+        stream::unfold(Some(self), |src| async move {
+            let src = src?;
+            match G::read_next_async(src).await {
+                Ok(Some(msg)) => Some((Ok(msg), Some(src))),
+                Ok(None) => None,
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+}
+
+/// `AsyncDataSink` allows user-defined async IO, serialization, and
+/// error handling.
+///
+/// This is the async equivalent of [`DataSink`]; see its documentation
+/// for more details.
+///
+/// [`DataSink`]: super::DataSink
+///
+pub trait AsyncDataSink: Send {
+    /// A user-defined error type.
+    type Error: Send;
+
+    /// Write a header and message to the data sink.
+    ///
+    fn write_message<T>(&mut self, msg: &T) -> impl Future<Output = Result<(), Self::Error>> + Send
+    where
+        T: Serialize + Versioned,
+        T::Base: MessageId;
+}
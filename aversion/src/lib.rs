@@ -30,11 +30,13 @@
 //! To make this work, structs must follow a particular pattern:
 //! - Versioned structs must follow the naming
 //!   convention `Name` + `V` + `{integer}`, i.e. `FooV1` or `BarV42`.
-//! - Versions must start at 1, and be contiguous.
+//! - Versions must start at 1, and be contiguous. Alternatively, the supported
+//!   versions can be listed on the latest version, e.g.
+//!   `#[aversion(versions(3, 4, 6))]` or `#[aversion(min_version = 3)]`.
 //! - There must be a type alias `type Foo = FooV3` that points to the latest
 //!   version.
 //! - For each pair of versions, `N` and `N+1`, the trait `FromVersion` must be
-//!   implemented. For example:
+//!   implemented (or `TryFromVersion`, if the upgrade can fail). For example:
 //! ```
 //! # use aversion::{FromVersion, Versioned};
 //! # #[derive(Versioned)]
@@ -50,6 +52,18 @@
 //! }
 //! ```
 //!
+//! Types that don't follow the naming convention can declare their base
+//! type and version with an attribute instead:
+//! ```
+//! # use aversion::Versioned;
+//! # type Foo = FooCurrent;
+//! #[derive(Versioned)]
+//! #[aversion(base = Foo, version = 3)]
+//! struct FooCurrent {
+//!     val: u64,
+//! }
+//! ```
+//!
 //! This crate is still new, and these rules may evolve in the future.
 //!
 //! ### Deserialization
@@ -104,24 +118,60 @@
 //! or `FooV2`) and `read_message` deserializes the correct version of the struct,
 //! upgrades it to the latest version, and returns it as a `MyProtocol`
 //! enum, for the caller to handle.
+//!
+//! The trait `GroupSerialize` can also be derived, to write any of the
+//! messages in `MyProtocol` along with the correct header:
+//! ```ignore
+//! let outgoing_message = MyProtocol::Bar(my_bar);
+//! outgoing_message.write_message(&mut my_data_sink)?;
+//! ```
+//!
+//! ## `no_std` support
+//!
+//! The `std` feature is enabled by default. Without it, this crate is
+//! `no_std` (but requires `alloc`): the core traits, the derive macros,
+//! and the message headers in [`util`] are still available, and headers
+//! can be encoded into byte slices using [`util::io`]. The data sources
+//! and sinks, and the serialization format features, require `std`.
+//!
+//! ## Features
+//!
+//! - `std` (default): use the standard library.
+//! - `serde_cbor` (default): `CborData`, using the CBOR format.
+//! - `bincode`, `rmp-serde`, `postcard`: `BincodeData`, `MsgpackData` and
+//!   `PostcardData`, using the bincode, MessagePack and postcard formats.
+//! - `serde_json`: `JsonLinesData`, which writes each message as a line
+//!   of JSON.
+//! - `async`: async data source and sink traits, and `AsyncFramedData`,
+//!   using tokio.
+//! - `codec`: `AversionCodec`, a tokio-util `Decoder` and `Encoder` for
+//!   message groups.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![warn(missing_docs)]
 #![forbid(unsafe_code)]
 #![warn(clippy::cast_possible_truncation)]
 
+extern crate alloc;
+
+mod error;
 pub mod group;
 mod id;
 pub mod util;
 mod versioned;
 
 #[doc(inline)]
-pub use crate::versioned::{FromVersion, IntoVersion, Versioned};
+pub use crate::versioned::{
+    DowngradeTo, FromVersion, IntoVersion, TryFromVersion, TryIntoVersion, Versioned,
+};
 
 #[doc(inline)]
-pub use crate::group::GroupDeserialize;
+pub use crate::group::{GroupDeserialize, GroupSerialize};
 
 #[doc(inline)]
-pub use aversion_macros::{GroupDeserialize, UpgradeLatest, Versioned};
+pub use aversion_macros::{
+    DowngradeTo, GroupDeserialize, GroupSerialize, UpgradeLatest, Versioned,
+};
 
 /// Implement `MessageId` for a bunch of types at once.
 ///
@@ -157,8 +207,33 @@ pub use aversion_macros::{GroupDeserialize, UpgradeLatest, Versioned};
 /// }
 /// ```
 ///
+/// Message ids may also be specified on the variants of a message group
+/// enum, using `#[aversion(id = ...)]`; see [`GroupDeserialize`] for details.
+///
 #[doc(inline)]
 pub use aversion_macros::assign_message_ids;
 
 #[doc(inline)]
 pub use id::MessageId;
+
+/// Expands to the items passed to it if the `async` feature is enabled,
+/// and to nothing otherwise.
+///
+/// The derive macros wrap their async trait impls in this, so that
+/// their output doesn't depend on which features are enabled.
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "async")]
+macro_rules! __async_items {
+    ($($item:tt)*) => { $($item)* };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "async"))]
+macro_rules! __async_items {
+    ($($item:tt)*) => {};
+}
+
+#[doc(inline)]
+pub use error::Error;
@@ -0,0 +1,76 @@
+//! Provides a `DataSink` and `DataSource` using the bincode format.
+
+use crate::util::framed::{Format, FramedData, FramedDataError};
+use crate::util::BasicHeader;
+use ::bincode::{DefaultOptions, ErrorKind, Options};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{self, Read};
+
+/// Errors that may occur while reading or writing BincodeData data.
+pub type BincodeDataError = FramedDataError<::bincode::Error>;
+
+impl From<::bincode::Error> for BincodeDataError {
+    fn from(e: ::bincode::Error) -> Self {
+        match *e {
+            ErrorKind::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => FramedDataError::Eof,
+            ErrorKind::Io(e) => FramedDataError::Io(e),
+            kind => FramedDataError::Format(Box::new(kind)),
+        }
+    }
+}
+
+/// The bincode configuration used for message bodies.
+///
+/// This uses variable-length integers, which are usually smaller than
+/// the fixed-length encoding used by `bincode::serialize`.
+fn options() -> impl Options {
+    DefaultOptions::new()
+}
+
+/// The bincode serialization format.
+///
+/// This can be used as the [`Format`] of a [`FramedData`].
+#[derive(Debug)]
+pub struct Bincode;
+
+impl Format for Bincode {
+    type Error = BincodeDataError;
+    const SELF_DELIMITING: bool = true;
+
+    fn serialize<T>(msg: &T) -> Result<Vec<u8>, BincodeDataError>
+    where
+        T: Serialize,
+    {
+        Ok(options().serialize(msg)?)
+    }
+
+    fn deserialize_from<T>(r: &mut impl Read) -> Result<T, BincodeDataError>
+    where
+        T: DeserializeOwned,
+    {
+        Ok(options().deserialize_from(r)?)
+    }
+}
+
+/// A [`DataSource`] and/or [`DataSink`] using the bincode serialization format.
+///
+/// [`BincodeData`] works with any type that implements [`Read`] or [`Write`].
+/// That includes files, network sockets, and memory buffers.
+///
+/// It implements the [`DataSource`] trait if the inner type implements [`Read`],
+/// and implements the [`DataSink`] trait if the inner type implements [`Write`].
+///
+/// Messages are framed with a [`BasicHeader`], the same as [`CborData`].
+/// Because bincode is not self-describing, the reader and writer must
+/// agree on the exact layout of each message version.
+///
+/// To use a different header type, use `FramedData<Bincode, H, RW>` instead.
+///
+/// [`DataSource`]: crate::group::DataSource
+/// [`DataSink`]: crate::group::DataSink
+/// [`Read`]: std::io::Read
+/// [`Write`]: std::io::Write
+/// [`CborData`]: crate::util::cbor::CborData
+///
+pub type BincodeData<RW> = FramedData<Bincode, BasicHeader, RW>;
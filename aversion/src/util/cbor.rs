@@ -1,131 +1,226 @@
 //! Provides a `DataSink` and `DataSource` using the CBOR format.
+//!
+//! With the `async` feature enabled, this also provides an `AsyncDataSink`
+//! and `AsyncDataSource` using the same format.
 
-use crate::group::{DataSink, DataSource};
+#[cfg(feature = "async")]
+use crate::util::framed::AsyncFramedData;
+use crate::util::framed::{Format, FramedData, FramedError};
 use crate::util::BasicHeader;
-use crate::{MessageId, Versioned};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use std::convert::TryInto;
-use std::io::{self, Cursor, Read, Write};
+use std::error::Error;
+use std::io::{self, Read};
 use thiserror::Error;
 
 /// Errors that may occur while reading or writing CborData data.
+///
+/// Errors caused by another error (e.g. a failure to deserialize a message)
+/// keep it, and return it from [`Error::source`].
 #[derive(Debug, Error)]
 pub enum CborDataError {
     /// A `std::io::Error` occurred while reading or writing data.
     #[error("IO Error")]
-    Io(Option<io::Error>),
+    Io(#[from] io::Error),
     /// An error occurred while serializing or deserializing data.
     #[error("Serialize/Deserialize Error")]
-    Serializer,
+    Serializer(#[source] serde_cbor::Error),
     /// An EOF happened while attempting to read data.
     #[error("Premature EOF")]
     Eof,
+    /// A message header's checksum didn't match the message.
+    #[error("Checksum Mismatch")]
+    Checksum,
+    /// A message was longer than the configured limit.
+    #[error("Message {msg_id} longer than {max_len} bytes")]
+    TooLarge {
+        /// The message id.
+        msg_id: u16,
+        /// The maximum message length.
+        max_len: u32,
+    },
+    /// A message with an unknown message id was received.
+    #[error("Unknown message id {id}")]
+    UnknownMessage {
+        /// The message id.
+        id: u16,
+    },
+    /// An unknown version of a known message was received.
+    #[error("Unknown version {ver} of {type_name}")]
+    UnknownVersion {
+        /// The name of the message type.
+        type_name: &'static str,
+        /// The message version.
+        ver: u16,
+    },
+    /// A specific message type was expected, but a different message was received.
+    #[error("Expected {expected}, got message id {got}")]
+    UnexpectedMessage {
+        /// The name of the expected message type.
+        expected: &'static str,
+        /// The message id that was received.
+        got: u16,
+    },
+    /// A message couldn't be deserialized.
+    #[error("Failed to decode message id {id} version {ver}")]
+    Decode {
+        /// The message id.
+        id: u16,
+        /// The message version.
+        ver: u16,
+        /// The deserialization error.
+        source: serde_cbor::Error,
+    },
+    /// A message was read, but upgrading it to the latest version failed.
+    #[error("Failed to upgrade version {ver} of {type_name}")]
+    Migration {
+        /// The name of the message type.
+        type_name: &'static str,
+        /// The message version.
+        ver: u16,
+        /// The error returned by the upgrade.
+        source: Box<dyn Error + Send + Sync>,
+    },
 }
 
+/// An EOF error is converted to [`CborDataError::Eof`], without keeping
+/// the original error, so that running out of data is reported the same
+/// way whether it's detected by the framing or by the deserializer. Other
+/// errors are kept as the source of [`CborDataError::Serializer`].
 impl From<serde_cbor::Error> for CborDataError {
     fn from(e: serde_cbor::Error) -> Self {
-        use serde_cbor::error::Category;
-
-        match e.classify() {
-            Category::Io => CborDataError::Io(None),
-            Category::Syntax => CborDataError::Serializer,
-            Category::Data => CborDataError::Serializer,
-            Category::Eof => CborDataError::Eof,
+        if e.is_eof() {
+            CborDataError::Eof
+        } else {
+            CborDataError::Serializer(e)
         }
     }
 }
 
-impl From<io::Error> for CborDataError {
-    fn from(e: io::Error) -> Self {
-        CborDataError::Io(Some(e))
-    }
-}
-
-/// A [`DataSource`] and/or [`DataSink`] using the CBOR serialization format.
-///
-/// [`CborData`] works with any type that implements [`Read`] or [`Write`].
-/// That includes files, network sockets, and memory buffers.
+/// The CBOR serialization format.
 ///
-/// It implements the [`DataSource`] trait if the inner type implements [`Read`],
-/// and implements the [`DataSink`] trait if the inner type implements [`Write`].
-///
-/// [`Read`]: std::io::Read
-/// [`Write`]: std::io::Write
-///
-pub struct CborData<RW> {
-    inner: RW,
-}
+/// This can be used as the [`Format`] of a [`FramedData`].
+#[derive(Debug)]
+pub struct Cbor;
 
-impl<RW> CborData<RW> {
-    /// Create a new `CborData`.
-    pub fn new(reader: RW) -> Self {
-        CborData { inner: reader }
-    }
+impl Format for Cbor {
+    type Error = CborDataError;
+    const SELF_DELIMITING: bool = true;
 
-    /// Consume the `CborData`, returning the inner data type.
-    pub fn into_inner(self) -> RW {
-        self.inner
+    fn serialize<T>(msg: &T) -> Result<Vec<u8>, CborDataError>
+    where
+        T: Serialize,
+    {
+        Ok(serde_cbor::to_vec(msg)?)
     }
-}
 
-impl<R> DataSource for CborData<R>
-where
-    R: Read,
-{
-    type Error = CborDataError;
-    type Header = BasicHeader;
-
-    fn read_header(&mut self) -> Result<BasicHeader, CborDataError> {
-        Ok(BasicHeader::deserialize_from(&mut self.inner)?)
+    fn deserialize_from<T>(r: &mut impl Read) -> Result<T, CborDataError>
+    where
+        T: DeserializeOwned,
+    {
+        // Don't use `serde_cbor::from_reader`, because it returns an error
+        // if there is more data after the message.
+        let mut deserializer = serde_cbor::Deserializer::from_reader(r);
+        Ok(T::deserialize(&mut deserializer)?)
     }
 
-    fn read_message<T>(&mut self, header: &BasicHeader) -> Result<T, CborDataError>
+    fn deserialize_exact<T>(r: &mut impl Read) -> Result<T, CborDataError>
     where
         T: DeserializeOwned,
     {
-        // Construct a reader over the exact message length specified
-        // in the message header.
-        let reader = &mut self.inner;
-        let mut subreader = reader.take(header.msg_len.into());
-        let msg: T = serde_cbor::from_reader(&mut subreader)?;
+        let mut deserializer = serde_cbor::Deserializer::from_reader(r);
+        let msg = T::deserialize(&mut deserializer)?;
+        deserializer.end()?;
         Ok(msg)
     }
+}
 
-    fn unknown_message(&self, _msg_id: u16) -> CborDataError {
-        CborDataError::Serializer
+impl From<crate::Error> for CborDataError {
+    fn from(e: crate::Error) -> Self {
+        match e {
+            crate::Error::UnknownMessage { msg_id } => CborDataError::UnknownMessage { id: msg_id },
+            crate::Error::UnknownVersion { type_name, ver } => {
+                CborDataError::UnknownVersion { type_name, ver }
+            }
+            crate::Error::UnexpectedMessage { expected, msg_id } => {
+                CborDataError::UnexpectedMessage {
+                    expected,
+                    got: msg_id,
+                }
+            }
+            crate::Error::MigrationFailed {
+                type_name,
+                ver,
+                source,
+            } => CborDataError::Migration {
+                type_name,
+                ver,
+                source,
+            },
+        }
     }
+}
 
-    fn unknown_version<T>(&self, _ver: u16) -> CborDataError {
-        CborDataError::Serializer
+impl FramedError for CborDataError {
+    fn eof() -> Self {
+        CborDataError::Eof
     }
 
-    fn unexpected_message<T>(&self, _msg_id: u16) -> CborDataError {
-        CborDataError::Serializer
+    fn checksum_mismatch() -> Self {
+        CborDataError::Checksum
     }
-}
 
-impl<W> DataSink for CborData<W>
-where
-    W: Write,
-{
-    type Error = CborDataError;
+    fn message_too_large(msg_id: u16, max_len: u32) -> Self {
+        CborDataError::TooLarge { msg_id, max_len }
+    }
 
-    fn write_message<T>(&mut self, msg: &T) -> Result<(), CborDataError>
-    where
-        T: Serialize + Versioned,
-        T::Base: MessageId,
-    {
-        // Serialize the message first, then the header (which needs
-        // the serialized message length.
-        let msg_buf = Vec::<u8>::new();
-        let mut cursor = Cursor::new(msg_buf);
-        serde_cbor::to_writer(&mut cursor, msg)?;
-        let msg_buf = cursor.into_inner();
-        let msg_len: u32 = msg_buf.len().try_into().expect("usize to u32");
-        let header = BasicHeader::for_msg(msg, msg_len);
-        header.serialize_into(&mut self.inner)?;
-        self.inner.write_all(&msg_buf)?;
-        Ok(())
+    fn with_message(self, msg_id: u16, msg_ver: u16) -> Self {
+        match self {
+            CborDataError::Serializer(source) => CborDataError::Decode {
+                id: msg_id,
+                ver: msg_ver,
+                source,
+            },
+            other => other,
+        }
     }
 }
+
+/// A [`DataSource`] and/or [`DataSink`] using the CBOR serialization format.
+///
+/// [`CborData`] works with any type that implements [`Read`] or [`Write`].
+/// That includes files, network sockets, and memory buffers.
+///
+/// It implements the [`DataSource`] trait if the inner type implements [`Read`],
+/// and implements the [`DataSink`] trait if the inner type implements [`Write`].
+///
+/// It also implements [`PeekHeader`], so the next header can be examined
+/// before deciding how to read the message.
+///
+/// This is a [`FramedData`] using a [`BasicHeader`]; to use a different
+/// header type, use `FramedData<Cbor, H, RW>` instead.
+///
+/// [`DataSource`]: crate::group::DataSource
+/// [`DataSink`]: crate::group::DataSink
+/// [`PeekHeader`]: crate::group::PeekHeader
+/// [`Read`]: std::io::Read
+/// [`Write`]: std::io::Write
+///
+pub type CborData<RW> = FramedData<Cbor, BasicHeader, RW>;
+
+/// An [`AsyncDataSource`] and/or [`AsyncDataSink`] using the CBOR serialization format.
+///
+/// This is the async equivalent of [`CborData`]. It works with any type that
+/// implements tokio's [`AsyncRead`] or [`AsyncWrite`], and uses the same
+/// message format, so data written by one can be read by the other.
+///
+/// This is an [`AsyncFramedData`] using a [`BasicHeader`]; to use a
+/// different header type, use `AsyncFramedData<Cbor, H, RW>` instead.
+///
+/// [`AsyncDataSource`]: crate::group::AsyncDataSource
+/// [`AsyncDataSink`]: crate::group::AsyncDataSink
+/// [`AsyncRead`]: tokio::io::AsyncRead
+/// [`AsyncWrite`]: tokio::io::AsyncWrite
+///
+#[cfg(feature = "async")]
+pub type AsyncCborData<RW> = AsyncFramedData<Cbor, BasicHeader, RW>;
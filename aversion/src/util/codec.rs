@@ -0,0 +1,183 @@
+//! Provides a tokio-util [`Decoder`] and [`Encoder`] for message groups.
+//!
+//! Messages are framed using a [`BasicHeader`], and serialized using the
+//! CBOR format, so the byte stream is the same as the one produced by
+//! [`CborData`].
+//!
+//! [`CborData`]: crate::util::cbor::CborData
+
+use crate::group::{DataSource, GroupDeserialize};
+use crate::util::cbor::{Cbor, CborData, CborDataError};
+use crate::util::framed::{check_header, encode_message, MessageLimits};
+use crate::util::BasicHeader;
+use crate::{MessageId, Versioned};
+use bytes::{Buf, BufMut, BytesMut};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// The length of a serialized [`BasicHeader`].
+const HEADER_LEN: usize = 8;
+
+/// The most buffer space to reserve at once, while waiting for a message.
+const RESERVE_CHUNK: usize = 64 * 1024;
+
+/// A tokio-util codec for a message group.
+///
+/// `AversionCodec` implements [`Decoder`], yielding values of the
+/// message group `G`, and implements [`Encoder`] for any message that
+/// belongs to a message group.
+///
+/// ```ignore
+/// let mut framed = Framed::new(tcp_stream, AversionCodec::<MyProto>::new());
+/// framed.send(&Foo { foo: 1 }).await?;
+/// let message: MyProto = framed.next().await.unwrap()?;
+/// ```
+///
+/// If `G` was derived with `#[aversion(skip_unknown)]`, skipped
+/// messages will be silently consumed by the decoder.
+///
+/// The length of each message is limited to
+/// [`MessageLimits::DEFAULT_MAX_LEN`], unless other limits are set with
+/// [`with_limits`][Self::with_limits]. The decoder grows its buffer as
+/// the message arrives, so a header alone can't cause a large allocation.
+///
+#[derive(Debug)]
+pub struct AversionCodec<G> {
+    limits: MessageLimits,
+    _group: PhantomData<fn() -> G>,
+}
+
+impl<G> AversionCodec<G> {
+    /// Create a new `AversionCodec`.
+    pub fn new() -> Self {
+        AversionCodec {
+            limits: MessageLimits::default(),
+            _group: PhantomData,
+        }
+    }
+
+    /// Set limits on the length of messages.
+    pub fn with_limits(mut self, limits: MessageLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+impl<G> Default for AversionCodec<G> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G> Clone for AversionCodec<G> {
+    fn clone(&self) -> Self {
+        AversionCodec {
+            limits: self.limits.clone(),
+            _group: PhantomData,
+        }
+    }
+}
+
+impl<G> Decoder for AversionCodec<G>
+where
+    G: GroupDeserialize,
+{
+    type Item = G;
+    type Error = CborDataError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<G>, CborDataError> {
+        loop {
+            if src.len() < HEADER_LEN {
+                src.reserve(HEADER_LEN - src.len());
+                return Ok(None);
+            }
+            let header = BasicHeader::deserialize_from(&mut &src[..HEADER_LEN])?;
+            check_header::<Cbor, _>(&header, &self.limits)?;
+            let msg_len = usize::try_from(header.msg_len).ok();
+            let frame_len = msg_len
+                .and_then(|msg_len| HEADER_LEN.checked_add(msg_len))
+                .ok_or_else(|| {
+                    let msg_id = header.msg_id;
+                    CborDataError::TooLarge {
+                        msg_id,
+                        max_len: self.limits.max_len_for(msg_id),
+                    }
+                })?;
+            if src.len() < frame_len {
+                src.reserve((frame_len - src.len()).min(RESERVE_CHUNK));
+                return Ok(None);
+            }
+
+            src.advance(HEADER_LEN);
+            let body = src.split_to(frame_len - HEADER_LEN);
+            let mut frame = FrameSource {
+                header: Some(header),
+                body: CborData::new(&body[..]),
+                skipped: false,
+            };
+            match G::read_message(&mut frame) {
+                // The group skipped over this message, and then found no
+                // more headers in the frame; try the next one.
+                Err(CborDataError::Eof) if frame.skipped => continue,
+                result => return result.map(Some),
+            }
+        }
+    }
+}
+
+impl<T, G> Encoder<&T> for AversionCodec<G>
+where
+    T: Serialize + Versioned,
+    T::Base: MessageId,
+{
+    type Error = CborDataError;
+
+    fn encode(&mut self, msg: &T, dst: &mut BytesMut) -> Result<(), CborDataError> {
+        let (header, msg_buf) = encode_message::<Cbor, BasicHeader, T>(msg, &self.limits)?;
+        dst.reserve(HEADER_LEN + msg_buf.len());
+        dst.put_slice(&header.serialize());
+        dst.put_slice(&msg_buf);
+        Ok(())
+    }
+}
+
+/// A `DataSource` containing exactly one message.
+///
+/// If the message is skipped, the group will try to read another header,
+/// which fails with [`CborDataError::Eof`]. The `skipped` flag allows the
+/// decoder to tell that apart from a real error. It is only set once the
+/// message has been skipped successfully.
+struct FrameSource<'a> {
+    header: Option<BasicHeader>,
+    body: CborData<&'a [u8]>,
+    skipped: bool,
+}
+
+impl DataSource for FrameSource<'_> {
+    type Error = CborDataError;
+    type Header = BasicHeader;
+
+    fn read_header(&mut self) -> Result<BasicHeader, CborDataError> {
+        self.header.take().ok_or(CborDataError::Eof)
+    }
+
+    fn read_message<T>(&mut self, header: &BasicHeader) -> Result<T, CborDataError>
+    where
+        T: DeserializeOwned,
+    {
+        self.body.read_message(header)
+    }
+
+    fn read_raw_message(&mut self, header: &BasicHeader) -> Result<Vec<u8>, CborDataError> {
+        self.body.read_raw_message(header)
+    }
+
+    fn skip_message(&mut self, header: &BasicHeader) -> Result<(), CborDataError> {
+        self.body.skip_message(header)?;
+        self.skipped = true;
+        Ok(())
+    }
+}
@@ -0,0 +1,927 @@
+//! Provides a `DataSink` and `DataSource` for any header and serialization format.
+//!
+//! [`FramedData`] writes each message as a header followed by the
+//! serialized message. The header type is any [`FramedHeader`], and the
+//! serialization format is any [`Format`].
+//!
+//! With the `async` feature enabled, this module also provides
+//! `AsyncFramedData`, an `AsyncDataSink` and `AsyncDataSource` that uses
+//! the same message format.
+//!
+//! ```
+//! use aversion::util::cbor::Cbor;
+//! use aversion::util::framed::FramedData;
+//! use aversion::util::TinyHeader;
+//! # use aversion::group::DataSink;
+//! # use aversion::{assign_message_ids, Versioned};
+//! # use serde::{Deserialize, Serialize};
+//! # #[derive(Versioned, Serialize, Deserialize)]
+//! # struct FooV1 { foo: u32 }
+//! # type Foo = FooV1;
+//! # assign_message_ids! { Foo: 1 }
+//!
+//! let mut sink = FramedData::<Cbor, TinyHeader, _>::new(Vec::new());
+//! sink.write_message(&Foo { foo: 1 }).unwrap();
+//! ```
+
+use crate::group::{DataSink, DataSource, PeekHeader};
+use crate::util::{FramedHeader, SyncHeader};
+use crate::{MessageId, Versioned};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use std::mem;
+use thiserror::Error;
+
+#[cfg(feature = "async")]
+use crate::group::{AsyncDataSink, AsyncDataSource};
+#[cfg(feature = "async")]
+use std::future::Future;
+#[cfg(feature = "async")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// A serialization format that can be used with [`FramedData`].
+pub trait Format {
+    /// The error type for a [`FramedData`] using this format.
+    type Error: FramedError;
+
+    /// Whether the format can tell where a message ends.
+    ///
+    /// If not, the format can only be used with a header that contains
+    /// the message length (see [`FramedHeader::HAS_LENGTH`]). Creating a
+    /// [`FramedData`] with a header that doesn't is a compile error.
+    /// The default value is `false`.
+    const SELF_DELIMITING: bool = false;
+
+    /// Serialize a message.
+    fn serialize<T>(msg: &T) -> Result<Vec<u8>, Self::Error>
+    where
+        T: Serialize;
+
+    /// Deserialize a message from a `Read` stream.
+    ///
+    /// If the header contains the message length, the stream will end
+    /// at the end of the message. Otherwise, the stream continues on to
+    /// the following messages, so the format must be able to tell where
+    /// each message ends.
+    fn deserialize_from<T>(r: &mut impl Read) -> Result<T, Self::Error>
+    where
+        T: DeserializeOwned;
+
+    /// Deserialize a message that fills the whole `Read` stream.
+    ///
+    /// This is used if the header contains the message length. An error
+    /// is returned if there is more data after the message.
+    ///
+    /// The default implementation calls
+    /// [`deserialize_from`][Self::deserialize_from], and then checks that
+    /// the stream is empty.
+    fn deserialize_exact<T>(r: &mut impl Read) -> Result<T, Self::Error>
+    where
+        T: DeserializeOwned,
+    {
+        let msg = Self::deserialize_from(r)?;
+        if r.read(&mut [0u8])? != 0 {
+            return Err(Self::Error::trailing_data());
+        }
+        Ok(msg)
+    }
+}
+
+/// An error type that can be returned by [`FramedData`].
+///
+/// These functions are used to construct errors that are specific to
+/// `FramedData`. Errors detected while reading a message group are
+/// converted from an [`aversion::Error`].
+///
+/// [`aversion::Error`]: crate::Error
+pub trait FramedError: From<io::Error> + From<crate::Error> {
+    /// The data ended partway through a message.
+    fn eof() -> Self;
+
+    /// The checksum in a message header didn't match the message.
+    fn checksum_mismatch() -> Self;
+
+    /// A message was longer than the [`MessageLimits`] allow.
+    fn message_too_large(msg_id: u16, max_len: u32) -> Self;
+
+    /// There was more data after a message than the header specified.
+    ///
+    /// The default implementation returns an [`InvalidData`] IO error.
+    ///
+    /// [`InvalidData`]: std::io::ErrorKind::InvalidData
+    fn trailing_data() -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, "trailing data after message").into()
+    }
+
+    /// Add the message id and version to an error returned by
+    /// [`Format::deserialize_from`].
+    ///
+    /// The default implementation returns the error unchanged.
+    fn with_message(self, _msg_id: u16, _msg_ver: u16) -> Self {
+        self
+    }
+}
+
+/// Errors that may occur while reading or writing framed data.
+///
+/// `E` is the error type of the serialization format. This is the error
+/// type of the bincode, MessagePack, postcard and JSON Lines formats.
+///
+/// Errors caused by another error (e.g. a failure to deserialize a message)
+/// keep it, and return it from [`Error::source`].
+///
+/// [`Error::source`]: std::error::Error::source
+#[derive(Debug, Error)]
+pub enum FramedDataError<E> {
+    /// A `std::io::Error` occurred while reading or writing data.
+    #[error("IO Error")]
+    Io(#[from] io::Error),
+    /// An error occurred while serializing or deserializing data.
+    #[error("Serialize/Deserialize Error")]
+    Format(#[source] E),
+    /// An EOF happened while attempting to read data.
+    #[error("Premature EOF")]
+    Eof,
+    /// A message header's checksum didn't match the message.
+    #[error("Checksum Mismatch")]
+    Checksum,
+    /// A message was longer than the configured limit.
+    #[error("Message {msg_id} longer than {max_len} bytes")]
+    TooLarge {
+        /// The message id.
+        msg_id: u16,
+        /// The maximum message length.
+        max_len: u32,
+    },
+    /// A message was shorter than the length in its header.
+    #[error("Trailing data after message")]
+    TrailingData,
+    /// A message couldn't be deserialized.
+    #[error("Failed to decode message id {id} version {ver}")]
+    Decode {
+        /// The message id.
+        id: u16,
+        /// The message version.
+        ver: u16,
+        /// The deserialization error.
+        source: E,
+    },
+    /// An error was detected while reading a message group, e.g. an
+    /// unknown message or a failed upgrade.
+    #[error("Message Group Error")]
+    Group(#[from] crate::Error),
+}
+
+impl<E> FramedError for FramedDataError<E> {
+    fn eof() -> Self {
+        FramedDataError::Eof
+    }
+
+    fn checksum_mismatch() -> Self {
+        FramedDataError::Checksum
+    }
+
+    fn message_too_large(msg_id: u16, max_len: u32) -> Self {
+        FramedDataError::TooLarge { msg_id, max_len }
+    }
+
+    fn trailing_data() -> Self {
+        FramedDataError::TrailingData
+    }
+
+    fn with_message(self, msg_id: u16, msg_ver: u16) -> Self {
+        match self {
+            FramedDataError::Format(source) => FramedDataError::Decode {
+                id: msg_id,
+                ver: msg_ver,
+                source,
+            },
+            other => other,
+        }
+    }
+}
+
+/// Limits on the length of serialized messages.
+///
+/// A data source will return an error if a message header specifies
+/// a length longer than the limit, before reading the message, so that
+/// a corrupt or malicious header can't cause a large allocation. A data
+/// sink will return an error instead of writing a message that is too
+/// long.
+///
+/// There is a limit for all messages, which may be overridden for
+/// specific message ids. By default, messages may be up to
+/// [`DEFAULT_MAX_LEN`][Self::DEFAULT_MAX_LEN] bytes long; use
+/// [`unlimited`][Self::unlimited] to allow any length.
+///
+/// ```
+/// use aversion::util::framed::MessageLimits;
+///
+/// let limits = MessageLimits::new()
+///     .with_max_len(64 * 1024)
+///     .with_max_len_for(7, 16 * 1024 * 1024);
+/// assert_eq!(limits.max_len_for(1), 64 * 1024);
+/// assert_eq!(limits.max_len_for(7), 16 * 1024 * 1024);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MessageLimits {
+    max_len: u32,
+    per_message: HashMap<u16, u32>,
+}
+
+impl Default for MessageLimits {
+    fn default() -> Self {
+        MessageLimits {
+            max_len: Self::DEFAULT_MAX_LEN,
+            per_message: HashMap::new(),
+        }
+    }
+}
+
+impl MessageLimits {
+    /// The default maximum length of a message (8 MiB).
+    pub const DEFAULT_MAX_LEN: u32 = 8 * 1024 * 1024;
+
+    /// Create a new `MessageLimits`, with the default limit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new `MessageLimits` that allows messages of any length.
+    ///
+    /// This should only be used if the data comes from a trusted source.
+    pub fn unlimited() -> Self {
+        Self::new().with_max_len(u32::MAX)
+    }
+
+    /// Set the maximum length for all messages.
+    pub fn with_max_len(mut self, max_len: u32) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Set the maximum length for messages with a specific message id.
+    ///
+    /// This overrides the limit set by [`with_max_len`][Self::with_max_len],
+    /// and may be larger or smaller.
+    pub fn with_max_len_for(mut self, msg_id: u16, max_len: u32) -> Self {
+        self.per_message.insert(msg_id, max_len);
+        self
+    }
+
+    /// The maximum length for messages with a specific message id.
+    pub fn max_len_for(&self, msg_id: u16) -> u32 {
+        match self.per_message.get(&msg_id) {
+            Some(&max_len) => max_len,
+            None => self.max_len,
+        }
+    }
+
+    /// The largest limit for any message id.
+    #[cfg(feature = "serde_json")]
+    pub(crate) fn max_len_any(&self) -> u32 {
+        self.per_message
+            .values()
+            .copied()
+            .fold(self.max_len, u32::max)
+    }
+
+    /// Check a message length against the limit for its message id.
+    ///
+    /// Returns the length as a `u32`, or the limit if the message is too long.
+    pub(crate) fn check(&self, msg_id: u16, len: u64) -> Result<u32, u32> {
+        let max_len = self.max_len_for(msg_id);
+        match u32::try_from(len) {
+            Ok(len) if len <= max_len => Ok(len),
+            _ => Err(max_len),
+        }
+    }
+}
+
+/// A [`DataSource`] and/or [`DataSink`] using any header and serialization format.
+///
+/// `F` is the serialization [`Format`], and `H` is the [`FramedHeader`] that
+/// is written before each message. `FramedData` works with any type that
+/// implements [`Read`] or [`Write`].
+///
+/// It implements the [`DataSource`] trait if the inner type implements [`Read`],
+/// and implements the [`DataSink`] trait if the inner type implements [`Write`].
+///
+/// Unknown messages can only be skipped if the header contains the
+/// message length. If the header contains a checksum, each message
+/// is verified before it's deserialized.
+///
+/// The length of each message is limited to
+/// [`MessageLimits::DEFAULT_MAX_LEN`], unless other limits are set with
+/// [`with_limits`][Self::with_limits]. If a header specifies a message
+/// that is too long, the message is not read, and the data that follows
+/// is not usable: further reads will try to read the message body as a
+/// header. If the header is a [`SyncHeader`], [`resync`][Self::resync]
+/// can be used to find the next header.
+///
+/// If deserializing a message fails, and the header contains the message
+/// length, the rest of the message is skipped so that the next message can
+/// still be read. If the header is a [`SyncHeader`], [`resync`][Self::resync]
+/// can be used to find the next header after corrupt data.
+///
+/// [`Read`]: std::io::Read
+/// [`Write`]: std::io::Write
+///
+pub struct FramedData<F, H, RW> {
+    inner: RW,
+    /// A header that has been read by `peek_header`, but not yet consumed.
+    peeked: Option<H>,
+    /// The bytes of a header that couldn't be read, for `resync` to search.
+    bad_header: Vec<u8>,
+    limits: MessageLimits,
+    _format: PhantomData<fn() -> F>,
+}
+
+impl<F, H, RW> FramedData<F, H, RW>
+where
+    F: Format,
+    H: FramedHeader,
+{
+    /// Fails to compile if `F` can't find the end of a message, and `H`
+    /// doesn't contain the message length.
+    const CHECK_FRAMING: () = assert!(
+        F::SELF_DELIMITING || H::HAS_LENGTH,
+        "this format needs a header that contains the message length"
+    );
+
+    /// Create a new `FramedData`.
+    pub fn new(inner: RW) -> Self {
+        let () = Self::CHECK_FRAMING;
+        FramedData {
+            inner,
+            peeked: None,
+            bad_header: Vec::new(),
+            limits: MessageLimits::default(),
+            _format: PhantomData,
+        }
+    }
+}
+
+impl<F, H, RW> FramedData<F, H, RW> {
+    /// Set limits on the length of messages.
+    pub fn with_limits(mut self, limits: MessageLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Consume the `FramedData`, returning the inner data type.
+    ///
+    /// If a header was peeked but not consumed, it will be lost.
+    pub fn into_inner(self) -> RW {
+        self.inner
+    }
+}
+
+impl<F, H, R> FramedData<F, H, R>
+where
+    F: Format,
+    H: FramedHeader,
+    R: Read,
+{
+    /// Read a header, or return `None` if the data ended before the header.
+    fn try_read_header(&mut self) -> Result<Option<H>, F::Error> {
+        // Read the first byte by itself: if there isn't one, the data
+        // ended on a message boundary.
+        match self.read_byte()? {
+            Some(byte) => Ok(Some(self.read_new_header(&[byte])?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Read one byte, or return `None` at the end of the data.
+    fn read_byte(&mut self) -> Result<Option<u8>, io::Error> {
+        let mut byte = [0u8; 1];
+        loop {
+            match self.inner.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Read a header from the inner stream.
+    ///
+    /// `first` contains any bytes of the header that have already been read.
+    fn read_new_header(&mut self, first: &[u8]) -> Result<H, F::Error> {
+        // Keep a copy of the header bytes, in case they need to be searched
+        // by `resync`.
+        self.bad_header.clear();
+        self.bad_header.extend_from_slice(first);
+        let recorder = Recorder {
+            inner: &mut self.inner,
+            record: &mut self.bad_header,
+        };
+        let header =
+            H::deserialize_from(&mut Read::chain(first, recorder)).map_err(header_error::<F>)?;
+        self.bad_header.clear();
+        Ok(header)
+    }
+
+    /// Check a header against the limits, before returning it.
+    fn accept_header(&mut self, header: H) -> Result<H, F::Error> {
+        if let Err(e) = check_header::<F, H>(&header, &self.limits) {
+            // Keep the header bytes, so that `resync` will search for
+            // the next header after this one.
+            self.bad_header.clear();
+            header.serialize_into(&mut self.bad_header)?;
+            return Err(e);
+        }
+        Ok(header)
+    }
+}
+
+impl<F, H, R> FramedData<F, H, R>
+where
+    F: Format,
+    H: SyncHeader,
+    R: Read,
+{
+    /// Scan forward to the next valid header.
+    ///
+    /// This can be used to recover after an error caused by corrupt data.
+    /// Bytes are discarded until a [`SYNC_MARKER`] is found that begins
+    /// a valid header; that header will be returned by the next call to
+    /// `read_header`. If there is no valid header before the end of the
+    /// data, all the remaining data is discarded.
+    ///
+    /// If the last header couldn't be read, or specified a message longer
+    /// than the limits allow, the search starts at the beginning of that
+    /// header.
+    ///
+    /// Returns the number of bytes that were discarded.
+    ///
+    /// [`SYNC_MARKER`]: SyncHeader::SYNC_MARKER
+    pub fn resync(&mut self) -> Result<u64, F::Error> {
+        if self.peeked.is_some() {
+            return Ok(0);
+        }
+        let marker = H::SYNC_MARKER;
+        let mut skipped = 0;
+        // Bytes that may be the start of a header.
+        let mut buf = mem::take(&mut self.bad_header);
+        loop {
+            // Discard any bytes before a (possibly partial) marker.
+            let start = (0..buf.len())
+                .find(|&i| buf[i..].iter().zip(marker).all(|(a, b)| a == b))
+                .unwrap_or(buf.len());
+            buf.drain(..start);
+            skipped += start as u64;
+
+            if buf.len() >= marker.len() {
+                match H::deserialize_from(&mut buf.as_slice()) {
+                    Ok(header) if check_header::<F, H>(&header, &self.limits).is_ok() => {
+                        self.peeked = Some(header);
+                        return Ok(skipped);
+                    }
+                    // The header may be valid, once the rest of it is read.
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+                    // Not a valid header, or the message is too long; look
+                    // for the next marker.
+                    _ => {
+                        buf.remove(0);
+                        skipped += 1;
+                        continue;
+                    }
+                }
+            }
+
+            match self.read_byte()? {
+                Some(byte) => buf.push(byte),
+                None => return Ok(skipped + buf.len() as u64),
+            }
+        }
+    }
+}
+
+impl<F, H, R> DataSource for FramedData<F, H, R>
+where
+    F: Format,
+    H: FramedHeader,
+    R: Read,
+{
+    type Error = F::Error;
+    type Header = H;
+
+    fn read_header(&mut self) -> Result<H, F::Error> {
+        let header = match self.peeked.take() {
+            Some(header) => header,
+            None => self.read_new_header(&[])?,
+        };
+        self.accept_header(header)
+    }
+
+    fn read_message<T>(&mut self, header: &H) -> Result<T, F::Error>
+    where
+        T: DeserializeOwned,
+    {
+        if H::HAS_CHECKSUM {
+            // The whole message is needed to verify the checksum.
+            let msg_buf = self.read_raw_message(header)?;
+            return deserialize_message::<F, H, T>(&mut msg_buf.as_slice(), header);
+        }
+        match header.msg_len() {
+            Some(msg_len) => {
+                // Construct a reader over the exact message length specified
+                // in the message header.
+                let reader = &mut self.inner;
+                let mut subreader = reader.take(msg_len.into());
+                let result = deserialize_message::<F, H, T>(&mut subreader, header);
+                // Always consume the whole message, even if deserializing
+                // failed, so that the next header can be read.
+                io::copy(&mut subreader, &mut io::sink())?;
+                if subreader.limit() != 0 {
+                    return Err(F::Error::eof());
+                }
+                result
+            }
+            None => {
+                // The format must find the end of the message, but
+                // it can't read past the length limit.
+                let msg_id = header.msg_id();
+                let max_len = self.limits.max_len_for(msg_id);
+                let reader = &mut self.inner;
+                let mut subreader = reader.take(max_len.into());
+                let result = deserialize_message::<F, H, T>(&mut subreader, header);
+                if result.is_err() && subreader.limit() == 0 {
+                    return Err(F::Error::message_too_large(msg_id, max_len));
+                }
+                result
+            }
+        }
+    }
+
+    fn read_raw_message(&mut self, header: &H) -> Result<Vec<u8>, F::Error> {
+        let msg_len = match header.msg_len() {
+            Some(msg_len) => msg_len,
+            None => return Err(self.unknown_message(header.msg_id())),
+        };
+        let reader = &mut self.inner;
+        let mut raw_bytes = Vec::new();
+        reader.take(msg_len.into()).read_to_end(&mut raw_bytes)?;
+        check_raw_message::<F, H>(header, msg_len, &raw_bytes)?;
+        Ok(raw_bytes)
+    }
+
+    fn skip_message(&mut self, header: &H) -> Result<(), F::Error> {
+        let msg_len = match header.msg_len() {
+            Some(msg_len) => msg_len,
+            None => return Err(self.unknown_message(header.msg_id())),
+        };
+        if H::HAS_CHECKSUM {
+            // Verify the checksum, even though the message isn't needed.
+            return self.read_raw_message(header).map(drop);
+        }
+        let reader = &mut self.inner;
+        let skipped = io::copy(&mut reader.take(msg_len.into()), &mut io::sink())?;
+        if skipped != u64::from(msg_len) {
+            return Err(F::Error::eof());
+        }
+        Ok(())
+    }
+
+    fn at_eof(&mut self) -> Result<bool, F::Error> {
+        if self.peeked.is_none() {
+            // Keep the header (if any) for the next `read_header`, which
+            // will check it against the limits.
+            self.peeked = self.try_read_header()?;
+        }
+        Ok(self.peeked.is_none())
+    }
+}
+
+impl<F, H, R> PeekHeader for FramedData<F, H, R>
+where
+    F: Format,
+    H: FramedHeader,
+    R: Read,
+{
+    fn peek_header(&mut self) -> Result<&H, F::Error> {
+        let header = match self.peeked.take() {
+            Some(header) => header,
+            None => self.read_new_header(&[])?,
+        };
+        let header = self.accept_header(header)?;
+        Ok(self.peeked.insert(header))
+    }
+}
+
+impl<F, H, W> DataSink for FramedData<F, H, W>
+where
+    F: Format,
+    H: FramedHeader,
+    W: Write,
+{
+    type Error = F::Error;
+
+    fn write_message<T>(&mut self, msg: &T) -> Result<(), F::Error>
+    where
+        T: Serialize + Versioned,
+        T::Base: MessageId,
+    {
+        let (header, msg_buf) = encode_message::<F, H, T>(msg, &self.limits)?;
+        header.serialize_into(&mut self.inner)?;
+        self.inner.write_all(&msg_buf)?;
+        Ok(())
+    }
+}
+
+/// An [`AsyncDataSource`] and/or [`AsyncDataSink`] using any header and
+/// serialization format.
+///
+/// This is the async equivalent of [`FramedData`]. It works with any type
+/// that implements tokio's [`AsyncRead`] or [`AsyncWrite`], and uses the
+/// same message format, so data written by one can be read by the other.
+///
+/// It implements the [`AsyncDataSource`] trait if the inner type implements
+/// [`AsyncRead`], and implements the [`AsyncDataSink`] trait if the inner type
+/// implements [`AsyncWrite`].
+///
+/// Each message is read into memory before it's deserialized, so the
+/// header must contain the message length; creating an `AsyncFramedData`
+/// with a header that doesn't is a compile error. Headers are read one
+/// byte at a time, so a reader that isn't buffered should be wrapped in
+/// a tokio `BufReader`.
+///
+/// The length of each message is limited to
+/// [`MessageLimits::DEFAULT_MAX_LEN`], unless other limits are set with
+/// [`with_limits`][Self::with_limits].
+///
+#[cfg(feature = "async")]
+pub struct AsyncFramedData<F, H, RW> {
+    inner: RW,
+    /// A header that has been read by `at_eof`, but not yet consumed.
+    peeked: Option<H>,
+    limits: MessageLimits,
+    _format: PhantomData<fn() -> F>,
+}
+
+#[cfg(feature = "async")]
+impl<F, H, RW> AsyncFramedData<F, H, RW>
+where
+    H: FramedHeader,
+{
+    /// Fails to compile if `H` doesn't contain the message length.
+    const CHECK_FRAMING: () = assert!(
+        H::HAS_LENGTH,
+        "AsyncFramedData needs a header that contains the message length"
+    );
+
+    /// Create a new `AsyncFramedData`.
+    pub fn new(inner: RW) -> Self {
+        let () = Self::CHECK_FRAMING;
+        AsyncFramedData {
+            inner,
+            peeked: None,
+            limits: MessageLimits::default(),
+            _format: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<F, H, RW> AsyncFramedData<F, H, RW> {
+    /// Set limits on the length of messages.
+    pub fn with_limits(mut self, limits: MessageLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Consume the `AsyncFramedData`, returning the inner data type.
+    ///
+    /// If a header was read by `at_eof` but not consumed, it will be lost.
+    pub fn into_inner(self) -> RW {
+        self.inner
+    }
+}
+
+#[cfg(feature = "async")]
+impl<F, H, R> AsyncFramedData<F, H, R>
+where
+    F: Format,
+    F::Error: Send,
+    H: FramedHeader + Send + Sync,
+    R: AsyncRead + Unpin + Send,
+{
+    /// Read a header, or return `None` if the data ended before the header.
+    async fn try_read_header(&mut self) -> Result<Option<H>, F::Error> {
+        // The header length isn't known until it has been read, so read
+        // one byte at a time until the header is complete.
+        let mut buf = Vec::new();
+        loop {
+            let mut byte = [0u8; 1];
+            if self.inner.read(&mut byte).await? == 0 {
+                if buf.is_empty() {
+                    return Ok(None);
+                }
+                return Err(F::Error::eof());
+            }
+            buf.push(byte[0]);
+            match H::deserialize_from(&mut buf.as_slice()) {
+                Ok(header) => return Ok(Some(header)),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<F, H, R> AsyncDataSource for AsyncFramedData<F, H, R>
+where
+    F: Format,
+    F::Error: Send,
+    H: FramedHeader + Send + Sync,
+    R: AsyncRead + Unpin + Send,
+{
+    type Error = F::Error;
+    type Header = H;
+
+    async fn read_header(&mut self) -> Result<H, F::Error> {
+        let header = match self.peeked.take() {
+            Some(header) => header,
+            None => self.try_read_header().await?.ok_or_else(F::Error::eof)?,
+        };
+        check_header::<F, H>(&header, &self.limits)?;
+        Ok(header)
+    }
+
+    async fn read_message<T>(&mut self, header: &H) -> Result<T, F::Error>
+    where
+        T: DeserializeOwned,
+    {
+        let msg_buf = self.read_raw_message(header).await?;
+        deserialize_message::<F, H, T>(&mut msg_buf.as_slice(), header)
+    }
+
+    async fn read_raw_message(&mut self, header: &H) -> Result<Vec<u8>, F::Error> {
+        let msg_len = match header.msg_len() {
+            Some(msg_len) => msg_len,
+            None => return Err(self.unknown_message(header.msg_id())),
+        };
+        let reader = &mut self.inner;
+        let mut raw_bytes = Vec::new();
+        reader
+            .take(msg_len.into())
+            .read_to_end(&mut raw_bytes)
+            .await?;
+        check_raw_message::<F, H>(header, msg_len, &raw_bytes)?;
+        Ok(raw_bytes)
+    }
+
+    async fn skip_message(&mut self, header: &H) -> Result<(), F::Error> {
+        // This verifies the checksum, if there is one.
+        self.read_raw_message(header).await.map(drop)
+    }
+
+    async fn at_eof(&mut self) -> Result<bool, F::Error> {
+        if self.peeked.is_none() {
+            // Keep the header (if any) for the next `read_header`, which
+            // will check it against the limits.
+            self.peeked = self.try_read_header().await?;
+        }
+        Ok(self.peeked.is_none())
+    }
+}
+
+#[cfg(feature = "async")]
+impl<F, H, W> AsyncDataSink for AsyncFramedData<F, H, W>
+where
+    F: Format,
+    F::Error: Send,
+    H: FramedHeader + Send,
+    W: AsyncWrite + Unpin + Send,
+{
+    type Error = F::Error;
+
+    fn write_message<T>(&mut self, msg: &T) -> impl Future<Output = Result<(), F::Error>> + Send
+    where
+        T: Serialize + Versioned,
+        T::Base: MessageId,
+    {
+        // Serialize before the first await, so the future doesn't need to hold `msg`.
+        let encoded = encode_message::<F, H, T>(msg, &self.limits).and_then(|(header, msg_buf)| {
+            let mut buf = Vec::new();
+            header.serialize_into(&mut buf)?;
+            buf.extend_from_slice(&msg_buf);
+            Ok(buf)
+        });
+        async move {
+            self.inner.write_all(&encoded?).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Convert an error from reading a header.
+///
+/// If the data ends partway through a header, that's reported as
+/// [`FramedError::eof`], the same as when a message is truncated.
+fn header_error<F>(e: io::Error) -> F::Error
+where
+    F: Format,
+{
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        F::Error::eof()
+    } else {
+        e.into()
+    }
+}
+
+/// Check the message length in a header against the limits.
+pub(crate) fn check_header<F, H>(header: &H, limits: &MessageLimits) -> Result<(), F::Error>
+where
+    F: Format,
+    H: FramedHeader,
+{
+    if let Some(msg_len) = header.msg_len() {
+        let msg_id = header.msg_id();
+        limits
+            .check(msg_id, msg_len.into())
+            .map_err(|max_len| F::Error::message_too_large(msg_id, max_len))?;
+    }
+    Ok(())
+}
+
+/// Serialize a message, and construct its header.
+pub(crate) fn encode_message<F, H, T>(
+    msg: &T,
+    limits: &MessageLimits,
+) -> Result<(H, Vec<u8>), F::Error>
+where
+    F: Format,
+    H: FramedHeader,
+    T: Serialize + Versioned,
+    T::Base: MessageId,
+{
+    // Serialize the message first, then the header (which needs
+    // the serialized message length.
+    let msg_buf = F::serialize(msg)?;
+    let msg_id = T::Base::MSG_ID;
+    let msg_len = limits
+        .check(msg_id, msg_buf.len() as u64)
+        .map_err(|max_len| F::Error::message_too_large(msg_id, max_len))?;
+    let mut header = H::from_parts(msg_id, T::VER, msg_len);
+    header.set_checksum(&msg_buf);
+    Ok((header, msg_buf))
+}
+
+/// Deserialize a message, adding the message id and version to any error.
+///
+/// If the header contains the message length, the message must fill
+/// the whole stream.
+fn deserialize_message<F, H, T>(r: &mut impl Read, header: &H) -> Result<T, F::Error>
+where
+    F: Format,
+    H: FramedHeader,
+    T: DeserializeOwned,
+{
+    let result = match header.msg_len() {
+        Some(_) => F::deserialize_exact(r),
+        None => F::deserialize_from(r),
+    };
+    result.map_err(|e| e.with_message(header.msg_id(), header.msg_ver()))
+}
+
+/// Check that the raw bytes of a message are complete, and match the
+/// header's checksum.
+fn check_raw_message<F, H>(header: &H, msg_len: u32, raw_bytes: &[u8]) -> Result<(), F::Error>
+where
+    F: Format,
+    H: FramedHeader,
+{
+    if raw_bytes.len() as u64 != u64::from(msg_len) {
+        return Err(F::Error::eof());
+    }
+    if !header.verify_checksum(raw_bytes) {
+        return Err(F::Error::checksum_mismatch());
+    }
+    Ok(())
+}
+
+/// A reader that keeps a copy of the bytes it reads.
+struct Recorder<'a, R> {
+    inner: &'a mut R,
+    record: &'a mut Vec<u8>,
+}
+
+impl<R> Read for Recorder<'_, R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.record.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+}
@@ -1,7 +1,83 @@
 use crate::group::GroupHeader;
+use crate::util::io::{self, read_u16, read_u32, read_u8, Read, Write};
 use crate::{MessageId, Versioned};
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use std::io::{self, Read, Write};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+/// A header that can be written before each message in a byte stream.
+///
+/// This is used by [`FramedData`] to read and write message headers,
+/// so that any header format can be combined with any serialization
+/// format.
+///
+/// [`FramedData`]: crate::util::framed::FramedData
+pub trait FramedHeader: GroupHeader + Sized {
+    /// A number identifying the header format.
+    ///
+    /// This is stored in a file [`Preamble`], so that data written with
+    /// one header type isn't read with another. The headers in this crate
+    /// use small numbers; user-defined headers should use a value of
+    /// `0x8000` or greater. The default value `0` means "unspecified".
+    ///
+    /// [`Preamble`]: crate::util::preamble::Preamble
+    const HEADER_KIND: u16 = 0;
+
+    /// Create a header for a message.
+    ///
+    /// `msg_len` is the length of the serialized message. Headers that
+    /// don't store the message length may ignore it.
+    fn from_parts(msg_id: u16, msg_ver: u16, msg_len: u32) -> Self;
+
+    /// Whether this header stores the message length.
+    ///
+    /// This must be `false` if [`msg_len`][Self::msg_len] returns `None`.
+    /// The default value is `true`.
+    const HAS_LENGTH: bool = true;
+
+    /// The length of the serialized message, if this header stores it.
+    fn msg_len(&self) -> Option<u32>;
+
+    /// Deserialize a header from a `Read` stream.
+    fn deserialize_from(r: &mut impl Read) -> Result<Self, io::Error>;
+
+    /// Serialize a header into a `Write` stream.
+    fn serialize_into(&self, w: &mut impl Write) -> Result<(), io::Error>;
+
+    /// Whether this header contains a checksum of the message.
+    ///
+    /// If it does, [`FramedData`] will read each message into a buffer
+    /// and call [`verify_checksum`][Self::verify_checksum] before
+    /// deserializing it.
+    ///
+    /// [`FramedData`]: crate::util::framed::FramedData
+    const HAS_CHECKSUM: bool = false;
+
+    /// Store a checksum of the serialized message in the header.
+    ///
+    /// This is called after [`from_parts`][Self::from_parts], before
+    /// the header is written. The default implementation does nothing.
+    fn set_checksum(&mut self, _msg: &[u8]) {}
+
+    /// Check the serialized message against the checksum in the header.
+    ///
+    /// The default implementation always returns `true`.
+    fn verify_checksum(&self, _msg: &[u8]) -> bool {
+        true
+    }
+}
+
+/// A header that begins with a fixed sync marker.
+///
+/// If the data is corrupt, [`FramedData::resync`] can scan forward
+/// for the marker, to find the next header. `deserialize_from` should
+/// return an error if the header doesn't start with the marker, and
+/// should be able to detect a false match (e.g. using a checksum).
+///
+/// [`FramedData::resync`]: crate::util::framed::FramedData::resync
+pub trait SyncHeader: FramedHeader {
+    /// The bytes at the start of every header.
+    const SYNC_MARKER: &'static [u8];
+}
 
 /// A header that can be serialized into a fixed-size buffer.
 ///
@@ -39,8 +115,8 @@ impl TinyHeader {
 
     /// Deserialize a header from a `Read` stream.
     pub fn deserialize_from(r: &mut impl Read) -> Result<Self, io::Error> {
-        let msg_id = r.read_u16::<BigEndian>()?;
-        let msg_ver = r.read_u16::<BigEndian>()?;
+        let msg_id = read_u16(r)?;
+        let msg_ver = read_u16(r)?;
         Ok(TinyHeader { msg_id, msg_ver })
     }
 
@@ -54,8 +130,8 @@ impl TinyHeader {
 
     /// Serialize a header into a `Write` stream.
     pub fn serialize_into(self, w: &mut impl Write) -> Result<(), io::Error> {
-        w.write_u16::<BigEndian>(self.msg_id)?;
-        w.write_u16::<BigEndian>(self.msg_ver)?;
+        w.write_all(&self.msg_id.to_be_bytes())?;
+        w.write_all(&self.msg_ver.to_be_bytes())?;
         Ok(())
     }
 
@@ -80,6 +156,29 @@ impl GroupHeader for TinyHeader {
     }
 }
 
+/// Because a `TinyHeader` doesn't contain the message length, the
+/// serialization format must be able to tell where each message ends.
+impl FramedHeader for TinyHeader {
+    const HEADER_KIND: u16 = 1;
+    const HAS_LENGTH: bool = false;
+
+    fn from_parts(msg_id: u16, msg_ver: u16, _msg_len: u32) -> Self {
+        TinyHeader::new(msg_id, msg_ver)
+    }
+
+    fn msg_len(&self) -> Option<u32> {
+        None
+    }
+
+    fn deserialize_from(r: &mut impl Read) -> Result<Self, io::Error> {
+        TinyHeader::deserialize_from(r)
+    }
+
+    fn serialize_into(&self, w: &mut impl Write) -> Result<(), io::Error> {
+        TinyHeader::serialize_into(*self, w)
+    }
+}
+
 /// A header that can be serialized into a fixed-size buffer.
 ///
 /// This header does not use serde; it serializes to a binary
@@ -126,9 +225,9 @@ impl BasicHeader {
 
     /// Deserialize a header from a `Read` stream.
     pub fn deserialize_from(r: &mut impl Read) -> Result<Self, io::Error> {
-        let msg_id = r.read_u16::<BigEndian>()?;
-        let msg_ver = r.read_u16::<BigEndian>()?;
-        let msg_len = r.read_u32::<BigEndian>()?;
+        let msg_id = read_u16(r)?;
+        let msg_ver = read_u16(r)?;
+        let msg_len = read_u32(r)?;
         Ok(BasicHeader {
             msg_id,
             msg_ver,
@@ -136,8 +235,8 @@ impl BasicHeader {
         })
     }
 
-    /// Deserialize a header from a 4-byte slice.
-    pub fn deserialize(buf: impl AsRef<[u8; 4]>) -> Self {
+    /// Deserialize a header from an 8-byte slice.
+    pub fn deserialize(buf: impl AsRef<[u8; 8]>) -> Self {
         // Use a &[u8] as the Read stream.
         let mut buf: &[u8] = buf.as_ref();
         // No io::Error is possible, since we're doing no actual IO.
@@ -146,15 +245,15 @@ impl BasicHeader {
 
     /// Serialize a header into a `Write` stream.
     pub fn serialize_into(self, w: &mut impl Write) -> Result<(), io::Error> {
-        w.write_u16::<BigEndian>(self.msg_id)?;
-        w.write_u16::<BigEndian>(self.msg_ver)?;
-        w.write_u32::<BigEndian>(self.msg_len)?;
+        w.write_all(&self.msg_id.to_be_bytes())?;
+        w.write_all(&self.msg_ver.to_be_bytes())?;
+        w.write_all(&self.msg_len.to_be_bytes())?;
         Ok(())
     }
 
-    /// Serialize a header into a 4-byte array.
-    pub fn serialize(self) -> [u8; 4] {
-        let mut buf = [0u8; 4];
+    /// Serialize a header into an 8-byte array.
+    pub fn serialize(self) -> [u8; 8] {
+        let mut buf = [0u8; 8];
         // Use a &[u8] as the Write stream.
         let mut cursor: &mut [u8] = buf.as_mut();
         // No io::Error is possible, since we're doing no actual IO.
@@ -172,3 +271,469 @@ impl GroupHeader for BasicHeader {
         self.msg_ver
     }
 }
+
+impl FramedHeader for BasicHeader {
+    const HEADER_KIND: u16 = 2;
+
+    fn from_parts(msg_id: u16, msg_ver: u16, msg_len: u32) -> Self {
+        BasicHeader::new(msg_id, msg_ver, msg_len)
+    }
+
+    fn msg_len(&self) -> Option<u32> {
+        Some(self.msg_len)
+    }
+
+    fn deserialize_from(r: &mut impl Read) -> Result<Self, io::Error> {
+        BasicHeader::deserialize_from(r)
+    }
+
+    fn serialize_into(&self, w: &mut impl Write) -> Result<(), io::Error> {
+        BasicHeader::serialize_into(*self, w)
+    }
+}
+
+/// A header that includes a checksum.
+///
+/// This header does not use serde; it serializes to a binary
+/// (big-endian) array of 12 bytes: the same fields as [`BasicHeader`],
+/// followed by a CRC-32 of those fields and the serialized message.
+///
+/// The checksum allows a data source to detect a corrupt header or
+/// message, rather than trying to deserialize it.
+#[derive(Debug, Clone, Copy)]
+pub struct ChecksumHeader {
+    /// The message id.
+    pub msg_id: u16,
+    /// The message version.
+    pub msg_ver: u16,
+    /// The length of the message when serialized.
+    pub msg_len: u32,
+    /// The CRC-32 of the other header fields and the serialized message.
+    pub crc: u32,
+}
+
+impl ChecksumHeader {
+    /// Create a new `ChecksumHeader` for a serialized message.
+    ///
+    /// The checksum will be computed from `msg`.
+    pub fn new(msg_id: u16, msg_ver: u16, msg_len: u32, msg: &[u8]) -> Self {
+        let mut header = ChecksumHeader {
+            msg_id,
+            msg_ver,
+            msg_len,
+            crc: 0,
+        };
+        header.crc = header.compute_crc(msg);
+        header
+    }
+
+    /// Compute the CRC-32 of the header fields and a serialized message.
+    ///
+    /// The stored `crc` value is not used.
+    pub fn compute_crc(&self, msg: &[u8]) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&self.msg_id.to_be_bytes());
+        hasher.update(&self.msg_ver.to_be_bytes());
+        hasher.update(&self.msg_len.to_be_bytes());
+        hasher.update(msg);
+        hasher.finalize()
+    }
+
+    /// Check a serialized message against the stored `crc` value.
+    pub fn verify(&self, msg: &[u8]) -> bool {
+        self.compute_crc(msg) == self.crc
+    }
+
+    /// Deserialize a header from a `Read` stream.
+    pub fn deserialize_from(r: &mut impl Read) -> Result<Self, io::Error> {
+        let msg_id = read_u16(r)?;
+        let msg_ver = read_u16(r)?;
+        let msg_len = read_u32(r)?;
+        let crc = read_u32(r)?;
+        Ok(ChecksumHeader {
+            msg_id,
+            msg_ver,
+            msg_len,
+            crc,
+        })
+    }
+
+    /// Deserialize a header from a 12-byte slice.
+    pub fn deserialize(buf: impl AsRef<[u8; 12]>) -> Self {
+        // Use a &[u8] as the Read stream.
+        let mut buf: &[u8] = buf.as_ref();
+        // No io::Error is possible, since we're doing no actual IO.
+        Self::deserialize_from(&mut buf).unwrap()
+    }
+
+    /// Serialize a header into a `Write` stream.
+    pub fn serialize_into(self, w: &mut impl Write) -> Result<(), io::Error> {
+        w.write_all(&self.msg_id.to_be_bytes())?;
+        w.write_all(&self.msg_ver.to_be_bytes())?;
+        w.write_all(&self.msg_len.to_be_bytes())?;
+        w.write_all(&self.crc.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Serialize a header into a 12-byte array.
+    pub fn serialize(self) -> [u8; 12] {
+        let mut buf = [0u8; 12];
+        // Use a &[u8] as the Write stream.
+        let mut cursor: &mut [u8] = buf.as_mut();
+        // No io::Error is possible, since we're doing no actual IO.
+        self.serialize_into(&mut cursor).unwrap();
+        buf
+    }
+}
+
+impl GroupHeader for ChecksumHeader {
+    fn msg_id(&self) -> u16 {
+        self.msg_id
+    }
+
+    fn msg_ver(&self) -> u16 {
+        self.msg_ver
+    }
+}
+
+impl FramedHeader for ChecksumHeader {
+    const HEADER_KIND: u16 = 3;
+    const HAS_CHECKSUM: bool = true;
+
+    fn from_parts(msg_id: u16, msg_ver: u16, msg_len: u32) -> Self {
+        ChecksumHeader {
+            msg_id,
+            msg_ver,
+            msg_len,
+            crc: 0,
+        }
+    }
+
+    fn msg_len(&self) -> Option<u32> {
+        Some(self.msg_len)
+    }
+
+    fn deserialize_from(r: &mut impl Read) -> Result<Self, io::Error> {
+        ChecksumHeader::deserialize_from(r)
+    }
+
+    fn serialize_into(&self, w: &mut impl Write) -> Result<(), io::Error> {
+        ChecksumHeader::serialize_into(*self, w)
+    }
+
+    fn set_checksum(&mut self, msg: &[u8]) {
+        self.crc = self.compute_crc(msg);
+    }
+
+    fn verify_checksum(&self, msg: &[u8]) -> bool {
+        self.verify(msg)
+    }
+}
+
+/// A header that starts with a sync marker, and includes a checksum.
+///
+/// This header does not use serde; it serializes to a binary
+/// (big-endian) array of 16 bytes: the [`SYNC_MARKER`], the same fields
+/// as [`BasicHeader`], and a CRC-32 of those fields.
+///
+/// Because a corrupt header can be detected, and the next header can be
+/// found by searching for the marker, a data source using this header
+/// can recover from corrupt data (see [`FramedData::resync`]).
+///
+/// [`SYNC_MARKER`]: SyncHeader::SYNC_MARKER
+/// [`FramedData::resync`]: crate::util::framed::FramedData::resync
+#[derive(Debug, Clone, Copy)]
+pub struct MarkedHeader {
+    /// The message id.
+    pub msg_id: u16,
+    /// The message version.
+    pub msg_ver: u16,
+    /// The length of the message when serialized.
+    pub msg_len: u32,
+}
+
+impl MarkedHeader {
+    /// Create a new `MarkedHeader`.
+    pub fn new(msg_id: u16, msg_ver: u16, msg_len: u32) -> Self {
+        MarkedHeader {
+            msg_id,
+            msg_ver,
+            msg_len,
+        }
+    }
+
+    /// Create a new `MarkedHeader` that corresponds to a type.
+    ///
+    /// The version and message id values will be filled in from
+    /// the type's [`Versioned`] and [`MessageId`] associated
+    /// constants.
+    pub fn for_msg<T>(_msg: &T, msg_len: u32) -> Self
+    where
+        T: Versioned,
+        T::Base: MessageId,
+    {
+        MarkedHeader {
+            msg_id: T::Base::MSG_ID,
+            msg_ver: T::VER,
+            msg_len,
+        }
+    }
+
+    /// Compute the CRC-32 of the header fields.
+    fn compute_crc(&self) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&self.msg_id.to_be_bytes());
+        hasher.update(&self.msg_ver.to_be_bytes());
+        hasher.update(&self.msg_len.to_be_bytes());
+        hasher.finalize()
+    }
+
+    /// Deserialize a header from a `Read` stream.
+    ///
+    /// An [`InvalidData`] error is returned if the header doesn't start
+    /// with the sync marker, or the checksum doesn't match.
+    ///
+    /// [`InvalidData`]: crate::util::io::ErrorKind::InvalidData
+    pub fn deserialize_from(r: &mut impl Read) -> Result<Self, io::Error> {
+        let mut marker = [0u8; 4];
+        r.read_exact(&mut marker)?;
+        if marker[..] != *Self::SYNC_MARKER {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "missing header sync marker",
+            ));
+        }
+        let msg_id = read_u16(r)?;
+        let msg_ver = read_u16(r)?;
+        let msg_len = read_u32(r)?;
+        let crc = read_u32(r)?;
+        let header = MarkedHeader {
+            msg_id,
+            msg_ver,
+            msg_len,
+        };
+        if crc != header.compute_crc() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "header checksum mismatch",
+            ));
+        }
+        Ok(header)
+    }
+
+    /// Deserialize a header from a 16-byte slice.
+    ///
+    /// An [`InvalidData`] error is returned if the header doesn't start
+    /// with the sync marker, or the checksum doesn't match.
+    ///
+    /// [`InvalidData`]: crate::util::io::ErrorKind::InvalidData
+    pub fn deserialize(buf: impl AsRef<[u8; 16]>) -> Result<Self, io::Error> {
+        // Use a &[u8] as the Read stream.
+        let mut buf: &[u8] = buf.as_ref();
+        Self::deserialize_from(&mut buf)
+    }
+
+    /// Serialize a header into a `Write` stream.
+    pub fn serialize_into(self, w: &mut impl Write) -> Result<(), io::Error> {
+        w.write_all(Self::SYNC_MARKER)?;
+        w.write_all(&self.msg_id.to_be_bytes())?;
+        w.write_all(&self.msg_ver.to_be_bytes())?;
+        w.write_all(&self.msg_len.to_be_bytes())?;
+        w.write_all(&self.compute_crc().to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Serialize a header into a 16-byte array.
+    pub fn serialize(self) -> [u8; 16] {
+        let mut buf = [0u8; 16];
+        // Use a &[u8] as the Write stream.
+        let mut cursor: &mut [u8] = buf.as_mut();
+        // No io::Error is possible, since we're doing no actual IO.
+        self.serialize_into(&mut cursor).unwrap();
+        buf
+    }
+}
+
+impl GroupHeader for MarkedHeader {
+    fn msg_id(&self) -> u16 {
+        self.msg_id
+    }
+
+    fn msg_ver(&self) -> u16 {
+        self.msg_ver
+    }
+}
+
+impl FramedHeader for MarkedHeader {
+    const HEADER_KIND: u16 = 5;
+
+    fn from_parts(msg_id: u16, msg_ver: u16, msg_len: u32) -> Self {
+        MarkedHeader::new(msg_id, msg_ver, msg_len)
+    }
+
+    fn msg_len(&self) -> Option<u32> {
+        Some(self.msg_len)
+    }
+
+    fn deserialize_from(r: &mut impl Read) -> Result<Self, io::Error> {
+        MarkedHeader::deserialize_from(r)
+    }
+
+    fn serialize_into(&self, w: &mut impl Write) -> Result<(), io::Error> {
+        MarkedHeader::serialize_into(*self, w)
+    }
+}
+
+impl SyncHeader for MarkedHeader {
+    const SYNC_MARKER: &'static [u8] = &[0xa5, 0x5a, 0xc3, 0x3c];
+}
+
+/// A header that uses variable-length integers.
+///
+/// Each field is encoded as an unsigned LEB128 integer, so small
+/// message ids, versions, and lengths take fewer bytes. A header for a
+/// message shorter than 128 bytes, with a small id and version, is only
+/// 3 bytes long.
+///
+/// Like [`BasicHeader`], this header includes a message length value.
+#[derive(Debug, Clone, Copy)]
+pub struct VarintHeader {
+    /// The message id.
+    pub msg_id: u16,
+    /// The message version.
+    pub msg_ver: u16,
+    /// The length of the message when serialized.
+    pub msg_len: u32,
+}
+
+impl VarintHeader {
+    /// Create a new `VarintHeader`.
+    pub fn new(msg_id: u16, msg_ver: u16, msg_len: u32) -> Self {
+        VarintHeader {
+            msg_id,
+            msg_ver,
+            msg_len,
+        }
+    }
+
+    /// Create a new `VarintHeader` that corresponds to a type.
+    ///
+    /// The version and message id values will be filled in from
+    /// the type's [`Versioned`] and [`MessageId`] associated
+    /// constants.
+    pub fn for_msg<T>(_msg: &T, msg_len: u32) -> Self
+    where
+        T: Versioned,
+        T::Base: MessageId,
+    {
+        VarintHeader {
+            msg_id: T::Base::MSG_ID,
+            msg_ver: T::VER,
+            msg_len,
+        }
+    }
+
+    /// Deserialize a header from a `Read` stream.
+    ///
+    /// An [`InvalidData`] error is returned if a value doesn't fit in
+    /// its field.
+    ///
+    /// [`InvalidData`]: crate::util::io::ErrorKind::InvalidData
+    pub fn deserialize_from(r: &mut impl Read) -> Result<Self, io::Error> {
+        let msg_id = read_varint(r)?;
+        let msg_ver = read_varint(r)?;
+        let msg_len = read_varint(r)?;
+        Ok(VarintHeader {
+            msg_id,
+            msg_ver,
+            msg_len,
+        })
+    }
+
+    /// Serialize a header into a `Write` stream.
+    pub fn serialize_into(self, w: &mut impl Write) -> Result<(), io::Error> {
+        write_varint(w, self.msg_id.into())?;
+        write_varint(w, self.msg_ver.into())?;
+        write_varint(w, self.msg_len.into())?;
+        Ok(())
+    }
+
+    /// Serialize a header into a byte vector.
+    pub fn serialize(self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        // No io::Error is possible, since we're doing no actual IO.
+        self.serialize_into(&mut buf).unwrap();
+        buf
+    }
+}
+
+impl GroupHeader for VarintHeader {
+    fn msg_id(&self) -> u16 {
+        self.msg_id
+    }
+
+    fn msg_ver(&self) -> u16 {
+        self.msg_ver
+    }
+}
+
+impl FramedHeader for VarintHeader {
+    const HEADER_KIND: u16 = 4;
+
+    fn from_parts(msg_id: u16, msg_ver: u16, msg_len: u32) -> Self {
+        VarintHeader::new(msg_id, msg_ver, msg_len)
+    }
+
+    fn msg_len(&self) -> Option<u32> {
+        Some(self.msg_len)
+    }
+
+    fn deserialize_from(r: &mut impl Read) -> Result<Self, io::Error> {
+        VarintHeader::deserialize_from(r)
+    }
+
+    fn serialize_into(&self, w: &mut impl Write) -> Result<(), io::Error> {
+        VarintHeader::serialize_into(*self, w)
+    }
+}
+
+/// Read an unsigned LEB128 integer.
+///
+/// An error is returned if the value doesn't fit in a `T`.
+fn read_varint<T>(r: &mut impl Read) -> Result<T, io::Error>
+where
+    T: TryFrom<u64>,
+{
+    let mut value: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = read_u8(r)?;
+        let low_bits = u64::from(byte & 0x7f);
+        // Check for overflow before shifting, so that extra bytes
+        // can't push the high bits off the end.
+        if shift >= 64 || (low_bits << shift) >> shift != low_bits {
+            return Err(varint_too_large());
+        }
+        value |= low_bits << shift;
+        if byte & 0x80 == 0 {
+            return T::try_from(value).map_err(|_| varint_too_large());
+        }
+        shift += 7;
+    }
+}
+
+/// Write an unsigned LEB128 integer.
+fn write_varint(w: &mut impl Write, mut value: u64) -> Result<(), io::Error> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return w.write_all(&[byte]);
+        }
+        w.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn varint_too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "varint value too large")
+}
@@ -0,0 +1,164 @@
+//! A minimal `Read`/`Write` abstraction, for encoding message headers.
+//!
+//! With the `std` feature enabled (the default), this module re-exports
+//! [`Read`], [`Write`], [`Error`] and [`ErrorKind`] from `std::io`, so
+//! headers can be read from and written to files, sockets, and so on.
+//!
+//! Without the `std` feature, this module provides minimal versions of
+//! those types instead. They are implemented for byte slices: `&[u8]` can
+//! be used as a [`Read`] stream, and `&mut [u8]` or `Vec<u8>` can be used
+//! as a [`Write`] stream. This allows headers such as [`TinyHeader`] and
+//! [`BasicHeader`] to be used on `no_std` targets.
+//!
+//! ```
+//! use aversion::util::BasicHeader;
+//!
+//! let header = BasicHeader::new(1, 2, 3);
+//! let mut buf = [0u8; 8];
+//! header.serialize_into(&mut buf.as_mut()).unwrap();
+//! let header = BasicHeader::deserialize_from(&mut buf.as_ref()).unwrap();
+//! assert_eq!(header.msg_len, 3);
+//! ```
+//!
+//! [`TinyHeader`]: crate::util::TinyHeader
+//! [`BasicHeader`]: crate::util::BasicHeader
+
+#[cfg(feature = "std")]
+#[doc(no_inline)]
+pub use std::io::{Error, ErrorKind, Read, Write};
+
+#[cfg(not(feature = "std"))]
+pub use self::slice::{Error, ErrorKind, Read, Write};
+
+/// Read a single byte.
+pub(crate) fn read_u8(r: &mut impl Read) -> Result<u8, Error> {
+    let mut buf = [0u8; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+/// Read a big-endian `u16`.
+pub(crate) fn read_u16(r: &mut impl Read) -> Result<u16, Error> {
+    let mut buf = [0u8; 2];
+    r.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+/// Read a big-endian `u32`.
+pub(crate) fn read_u32(r: &mut impl Read) -> Result<u32, Error> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+#[cfg(not(feature = "std"))]
+mod slice {
+    use alloc::vec::Vec;
+    use core::fmt;
+
+    /// The kind of an [`Error`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum ErrorKind {
+        /// The end of the input was reached before a value was read.
+        UnexpectedEof,
+        /// There wasn't enough space to write a value.
+        WriteZero,
+        /// A value was read, but it isn't valid.
+        InvalidData,
+    }
+
+    /// An error that occurred while reading or writing a header.
+    #[derive(Debug)]
+    pub struct Error {
+        kind: ErrorKind,
+        msg: &'static str,
+    }
+
+    impl Error {
+        /// Create a new `Error`.
+        pub fn new(kind: ErrorKind, msg: &'static str) -> Self {
+            Error { kind, msg }
+        }
+
+        /// The kind of error.
+        pub fn kind(&self) -> ErrorKind {
+            self.kind
+        }
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(self.msg)
+        }
+    }
+
+    impl core::error::Error for Error {}
+
+    /// A source of bytes.
+    pub trait Read {
+        /// Read exactly enough bytes to fill `buf`.
+        ///
+        /// An [`UnexpectedEof`][ErrorKind::UnexpectedEof] error is returned
+        /// if there aren't enough bytes.
+        fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error>;
+    }
+
+    /// A destination for bytes.
+    pub trait Write {
+        /// Write all of the bytes in `buf`.
+        ///
+        /// A [`WriteZero`][ErrorKind::WriteZero] error is returned if
+        /// there isn't enough space.
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), Error>;
+    }
+
+    impl Read for &[u8] {
+        fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+            if buf.len() > self.len() {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "failed to fill whole buffer",
+                ));
+            }
+            let (head, tail) = self.split_at(buf.len());
+            buf.copy_from_slice(head);
+            *self = tail;
+            Ok(())
+        }
+    }
+
+    impl Write for &mut [u8] {
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+            if buf.len() > self.len() {
+                return Err(Error::new(
+                    ErrorKind::WriteZero,
+                    "failed to write whole buffer",
+                ));
+            }
+            let (head, tail) = core::mem::take(self).split_at_mut(buf.len());
+            head.copy_from_slice(buf);
+            *self = tail;
+            Ok(())
+        }
+    }
+
+    impl Write for Vec<u8> {
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+            self.extend_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    impl<R: Read + ?Sized> Read for &mut R {
+        fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+            (**self).read_exact(buf)
+        }
+    }
+
+    impl<W: Write + ?Sized> Write for &mut W {
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+            (**self).write_all(buf)
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Provides a `DataSink` and `DataSource` using JSON Lines.
+//!
+//! Each message is written as a single line of JSON, wrapped in an
+//! envelope containing the message id and version:
+//!
+//! ```text
+//! {"id":123,"ver":2,"msg":{"foo":1}}
+//! ```
+//!
+//! This is less efficient than the binary formats, but is human-readable,
+//! and easy to produce or consume from other languages.
+
+use crate::group::{DataSink, DataSource, GroupHeader, PeekHeader};
+use crate::util::framed::{FramedDataError, MessageLimits};
+use crate::{MessageId, Versioned};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{self, BufRead, Read, Write};
+
+/// Errors that may occur while reading or writing JsonLinesData data.
+///
+/// JSON Lines data doesn't use message headers, so checksum errors aren't
+/// returned.
+pub type JsonLinesDataError = FramedDataError<serde_json::Error>;
+
+impl From<serde_json::Error> for JsonLinesDataError {
+    fn from(e: serde_json::Error) -> Self {
+        use serde_json::error::Category;
+
+        match e.classify() {
+            // This returns the original `io::Error`.
+            Category::Io => FramedDataError::Io(e.into()),
+            Category::Syntax | Category::Data => FramedDataError::Format(e),
+            Category::Eof => FramedDataError::Eof,
+        }
+    }
+}
+
+/// The header of a JSON Lines message.
+///
+/// Because each line contains both the header fields and the message,
+/// the header also holds the (not yet deserialized) message body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonHeader {
+    /// The message id.
+    pub msg_id: u16,
+    /// The message version.
+    pub msg_ver: u16,
+    /// The message, as a JSON value.
+    pub msg: Value,
+}
+
+impl GroupHeader for JsonHeader {
+    fn msg_id(&self) -> u16 {
+        self.msg_id
+    }
+
+    fn msg_ver(&self) -> u16 {
+        self.msg_ver
+    }
+}
+
+/// The envelope used when reading a line.
+#[derive(Deserialize)]
+struct Envelope {
+    id: u16,
+    ver: u16,
+    msg: Value,
+}
+
+/// The envelope used when writing a line.
+#[derive(Serialize)]
+struct EnvelopeRef<'a, T> {
+    id: u16,
+    ver: u16,
+    msg: &'a T,
+}
+
+/// A [`DataSource`] and/or [`DataSink`] using JSON Lines.
+///
+/// It implements the [`DataSource`] trait if the inner type implements
+/// [`BufRead`], and implements the [`DataSink`] trait if the inner type
+/// implements [`Write`].
+///
+/// Blank lines are ignored when reading. The raw bytes of an unknown
+/// message are the JSON text of its `msg` field.
+///
+/// The length of each line (not counting the newline) is limited to
+/// [`MessageLimits::DEFAULT_MAX_LEN`], unless other limits are set with
+/// [`with_limits`][Self::with_limits]. A line that is too long is skipped,
+/// and [`JsonLinesDataError::TooLarge`] is returned. If the line is longer
+/// than every limit, it isn't parsed, and the error has message id 0.
+///
+/// [`BufRead`]: std::io::BufRead
+/// [`Write`]: std::io::Write
+///
+pub struct JsonLinesData<RW> {
+    inner: RW,
+    /// A header that has been read by `peek_header`, but not yet consumed.
+    peeked: Option<JsonHeader>,
+    limits: MessageLimits,
+}
+
+impl<RW> JsonLinesData<RW> {
+    /// Create a new `JsonLinesData`.
+    pub fn new(inner: RW) -> Self {
+        JsonLinesData {
+            inner,
+            peeked: None,
+            limits: MessageLimits::default(),
+        }
+    }
+
+    /// Set limits on the length of lines.
+    pub fn with_limits(mut self, limits: MessageLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Consume the `JsonLinesData`, returning the inner data type.
+    ///
+    /// If a header was peeked but not consumed, it will be lost.
+    pub fn into_inner(self) -> RW {
+        self.inner
+    }
+}
+
+impl<R> JsonLinesData<R>
+where
+    R: BufRead,
+{
+    /// Skip over any blank lines, and report whether there is more data.
+    fn skip_blank_lines(&mut self) -> Result<bool, io::Error> {
+        loop {
+            let buf = self.inner.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(false);
+            }
+            let blank = buf.iter().take_while(|b| b.is_ascii_whitespace()).count();
+            if blank == 0 {
+                return Ok(true);
+            }
+            self.inner.consume(blank);
+        }
+    }
+
+    /// Discard the rest of the current line, including the newline.
+    fn skip_line(&mut self) -> Result<(), io::Error> {
+        loop {
+            let buf = self.inner.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(());
+            }
+            match buf.iter().position(|&b| b == b'\n') {
+                Some(pos) => {
+                    self.inner.consume(pos + 1);
+                    return Ok(());
+                }
+                None => {
+                    let len = buf.len();
+                    self.inner.consume(len);
+                }
+            }
+        }
+    }
+
+    /// Read the next line, and parse its envelope.
+    fn read_envelope(&mut self) -> Result<JsonHeader, JsonLinesDataError> {
+        if !self.skip_blank_lines()? {
+            return Err(FramedDataError::Eof);
+        }
+        // Read no more than the longest allowed line, plus the newline.
+        let max_len = self.limits.max_len_any();
+        let mut line = Vec::new();
+        (&mut self.inner)
+            .take(u64::from(max_len) + 1)
+            .read_until(b'\n', &mut line)?;
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        } else if line.len() > max_len as usize {
+            self.skip_line()?;
+            return Err(FramedDataError::TooLarge { msg_id: 0, max_len });
+        }
+        let envelope: Envelope = serde_json::from_slice(&line)?;
+        if let Err(max_len) = self.limits.check(envelope.id, line.len() as u64) {
+            return Err(FramedDataError::TooLarge {
+                msg_id: envelope.id,
+                max_len,
+            });
+        }
+        Ok(JsonHeader {
+            msg_id: envelope.id,
+            msg_ver: envelope.ver,
+            msg: envelope.msg,
+        })
+    }
+}
+
+impl<R> DataSource for JsonLinesData<R>
+where
+    R: BufRead,
+{
+    type Error = JsonLinesDataError;
+    type Header = JsonHeader;
+
+    fn read_header(&mut self) -> Result<JsonHeader, JsonLinesDataError> {
+        match self.peeked.take() {
+            Some(header) => Ok(header),
+            None => self.read_envelope(),
+        }
+    }
+
+    fn read_message<T>(&mut self, header: &JsonHeader) -> Result<T, JsonLinesDataError>
+    where
+        T: DeserializeOwned,
+    {
+        Ok(T::deserialize(&header.msg)?)
+    }
+
+    fn read_raw_message(&mut self, header: &JsonHeader) -> Result<Vec<u8>, JsonLinesDataError> {
+        Ok(serde_json::to_vec(&header.msg)?)
+    }
+
+    fn skip_message(&mut self, _header: &JsonHeader) -> Result<(), JsonLinesDataError> {
+        // The whole line was consumed when the header was read.
+        Ok(())
+    }
+
+    fn at_eof(&mut self) -> Result<bool, JsonLinesDataError> {
+        if self.peeked.is_some() {
+            return Ok(false);
+        }
+        Ok(!self.skip_blank_lines()?)
+    }
+}
+
+impl<R> PeekHeader for JsonLinesData<R>
+where
+    R: BufRead,
+{
+    fn peek_header(&mut self) -> Result<&JsonHeader, JsonLinesDataError> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.read_envelope()?);
+        }
+        Ok(self.peeked.as_ref().unwrap())
+    }
+}
+
+impl<W> DataSink for JsonLinesData<W>
+where
+    W: Write,
+{
+    type Error = JsonLinesDataError;
+
+    fn write_message<T>(&mut self, msg: &T) -> Result<(), JsonLinesDataError>
+    where
+        T: Serialize + Versioned,
+        T::Base: MessageId,
+    {
+        let envelope = EnvelopeRef {
+            id: T::Base::MSG_ID,
+            ver: T::VER,
+            msg,
+        };
+        // Serialize into a buffer first, so that a serialization error
+        // doesn't leave a partial line in the output.
+        let mut line = serde_json::to_vec(&envelope)?;
+        if let Err(max_len) = self.limits.check(T::Base::MSG_ID, line.len() as u64) {
+            return Err(FramedDataError::TooLarge {
+                msg_id: T::Base::MSG_ID,
+                max_len,
+            });
+        }
+        line.push(b'\n');
+        self.inner.write_all(&line)?;
+        Ok(())
+    }
+}
@@ -4,20 +4,94 @@
 //! just want to get started and don't want to implement the
 //! [`DataSource`] trait themselves.
 //!
-//! [`FixedHeader`] is a basic message header struct that implements
-//! the [`GroupHeader`] trait.
+//! [`TinyHeader`] and [`BasicHeader`] are basic message header structs
+//! that implement the [`GroupHeader`] trait. [`VarintHeader`] is a more
+//! compact header, for streams of small messages, and [`ChecksumHeader`]
+//! adds a CRC-32 so that corrupt data can be detected. [`MarkedHeader`]
+//! starts with a sync marker, so that a reader can find the next message
+//! after corrupt data.
+//!
+//! The headers are read and written using the [`io`] module, which is
+//! `std::io` when the `std` feature is enabled. Without it, headers can
+//! still be encoded into byte slices, on `no_std` targets.
+//!
+//! With the `std` feature enabled, the [`framed`] module includes
+//! [`FramedData`], a `DataSource`/`DataSink` that can combine any
+//! [`FramedHeader`] with any serialization [`Format`]. With the `async`
+//! feature enabled, it also includes [`AsyncFramedData`], which does the
+//! same over tokio's `AsyncRead` and `AsyncWrite`. The modules below
+//! provide formats, along with a `FramedData` type alias for each one that
+//! uses a [`BasicHeader`].
+//!
+//! The [`preamble`] module, which also requires `std`, includes wrappers
+//! for `FramedData` that write and validate a [`Preamble`] at the start of
+//! a file, so that message logs can be identified.
 //!
 //! The [`cbor`] module includes [`CborData`], a `DataSource`/`DataSink`
-//! that uses the CBOR serialization format for messages.
+//! that uses the CBOR serialization format for messages. With the `async`
+//! feature enabled, it also includes [`AsyncCborData`], which does the same
+//! over tokio's `AsyncRead` and `AsyncWrite`.
+//!
+//! With the `bincode` feature enabled, the [`bincode`] module includes
+//! [`BincodeData`], a `DataSource`/`DataSink` that uses the more compact
+//! (but not self-describing) bincode format.
+//!
+//! With the `rmp-serde` feature enabled, the [`msgpack`] module includes
+//! [`MsgpackData`], and with the `postcard` feature enabled, the
+//! [`postcard`] module includes [`PostcardData`]. These work the same way,
+//! using the MessagePack and postcard formats.
+//!
+//! With the `serde_json` feature enabled, the [`json`] module includes
+//! [`JsonLinesData`], a `DataSource`/`DataSink` that writes each message
+//! as a line of JSON, for debugging and interoperability.
+//!
+//! With the `codec` feature enabled, the [`codec`] module includes
+//! [`AversionCodec`], a tokio-util `Decoder`/`Encoder` that uses the same
+//! message format as `CborData`.
 //!
 //! [`DataSource`]: crate::group::DataSource
 //! [`GroupHeader`]: crate::group::GroupHeader
+//! [`FramedData`]: crate::util::framed::FramedData
+//! [`Format`]: crate::util::framed::Format
+//! [`AsyncFramedData`]: crate::util::framed::AsyncFramedData
+//! [`Preamble`]: crate::util::preamble::Preamble
 //! [`CborData`]: crate::util::cbor::CborData
+//! [`AsyncCborData`]: crate::util::cbor::AsyncCborData
+//! [`BincodeData`]: crate::util::bincode::BincodeData
+//! [`MsgpackData`]: crate::util::msgpack::MsgpackData
+//! [`PostcardData`]: crate::util::postcard::PostcardData
+//! [`JsonLinesData`]: crate::util::json::JsonLinesData
+//! [`AversionCodec`]: crate::util::codec::AversionCodec
 
 mod header;
 
 #[doc(inline)]
-pub use header::{BasicHeader, TinyHeader};
+pub use header::{
+    BasicHeader, ChecksumHeader, FramedHeader, MarkedHeader, SyncHeader, TinyHeader, VarintHeader,
+};
+
+pub mod io;
+
+#[cfg(feature = "std")]
+pub mod framed;
+
+#[cfg(feature = "std")]
+pub mod preamble;
 
 #[cfg(feature = "serde_cbor")]
 pub mod cbor;
+
+#[cfg(feature = "bincode")]
+pub mod bincode;
+
+#[cfg(feature = "rmp-serde")]
+pub mod msgpack;
+
+#[cfg(feature = "postcard")]
+pub mod postcard;
+
+#[cfg(feature = "serde_json")]
+pub mod json;
+
+#[cfg(feature = "codec")]
+pub mod codec;
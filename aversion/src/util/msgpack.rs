@@ -0,0 +1,94 @@
+//! Provides a `DataSink` and `DataSource` using the MessagePack format.
+
+use crate::util::framed::{Format, FramedData, FramedDataError};
+use crate::util::BasicHeader;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{self, Read};
+use thiserror::Error;
+
+/// An error returned by the MessagePack serializer or deserializer.
+#[derive(Debug, Error)]
+pub enum MsgpackError {
+    /// An error occurred while deserializing data.
+    #[error(transparent)]
+    Decode(#[from] rmp_serde::decode::Error),
+    /// An error occurred while serializing data.
+    #[error(transparent)]
+    Encode(#[from] rmp_serde::encode::Error),
+}
+
+/// Errors that may occur while reading or writing MsgpackData data.
+pub type MsgpackDataError = FramedDataError<MsgpackError>;
+
+impl From<rmp_serde::decode::Error> for MsgpackDataError {
+    fn from(e: rmp_serde::decode::Error) -> Self {
+        use rmp_serde::decode::Error;
+
+        match e {
+            Error::InvalidMarkerRead(e) | Error::InvalidDataRead(e)
+                if e.kind() == io::ErrorKind::UnexpectedEof =>
+            {
+                FramedDataError::Eof
+            }
+            Error::InvalidMarkerRead(e) | Error::InvalidDataRead(e) => FramedDataError::Io(e),
+            e => FramedDataError::Format(e.into()),
+        }
+    }
+}
+
+impl From<rmp_serde::encode::Error> for MsgpackDataError {
+    fn from(e: rmp_serde::encode::Error) -> Self {
+        FramedDataError::Format(e.into())
+    }
+}
+
+/// The MessagePack serialization format.
+///
+/// This can be used as the [`Format`] of a [`FramedData`].
+///
+/// Structs are serialized as maps with named fields, so that they are
+/// easy to use from other languages.
+#[derive(Debug)]
+pub struct Msgpack;
+
+impl Format for Msgpack {
+    type Error = MsgpackDataError;
+    const SELF_DELIMITING: bool = true;
+
+    fn serialize<T>(msg: &T) -> Result<Vec<u8>, MsgpackDataError>
+    where
+        T: Serialize,
+    {
+        Ok(rmp_serde::to_vec_named(msg)?)
+    }
+
+    fn deserialize_from<T>(r: &mut impl Read) -> Result<T, MsgpackDataError>
+    where
+        T: DeserializeOwned,
+    {
+        Ok(rmp_serde::from_read(r)?)
+    }
+}
+
+/// A [`DataSource`] and/or [`DataSink`] using the MessagePack serialization format.
+///
+/// [`MsgpackData`] works with any type that implements [`Read`] or [`Write`].
+/// That includes files, network sockets, and memory buffers.
+///
+/// It implements the [`DataSource`] trait if the inner type implements [`Read`],
+/// and implements the [`DataSink`] trait if the inner type implements [`Write`].
+///
+/// Messages are framed with a [`BasicHeader`], the same as [`CborData`].
+/// Structs are serialized as maps with named fields, so that they are
+/// easy to use from other languages.
+///
+/// To use a different header type, use `FramedData<Msgpack, H, RW>` instead.
+///
+/// [`DataSource`]: crate::group::DataSource
+/// [`DataSink`]: crate::group::DataSink
+/// [`Read`]: std::io::Read
+/// [`Write`]: std::io::Write
+/// [`CborData`]: crate::util::cbor::CborData
+///
+pub type MsgpackData<RW> = FramedData<Msgpack, BasicHeader, RW>;
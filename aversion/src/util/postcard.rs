@@ -0,0 +1,80 @@
+//! Provides a `DataSink` and `DataSource` using the postcard format.
+
+use crate::util::framed::{Format, FramedData, FramedDataError};
+use crate::util::BasicHeader;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::Read;
+
+/// Errors that may occur while reading or writing PostcardData data.
+pub type PostcardDataError = FramedDataError<::postcard::Error>;
+
+impl From<::postcard::Error> for PostcardDataError {
+    fn from(e: ::postcard::Error) -> Self {
+        match e {
+            ::postcard::Error::DeserializeUnexpectedEnd => FramedDataError::Eof,
+            e => FramedDataError::Format(e),
+        }
+    }
+}
+
+/// The postcard serialization format.
+///
+/// This can be used as the [`Format`] of a [`FramedData`].
+///
+/// postcard can't tell where a message ends, so it must be used with a
+/// header that contains the message length, like [`BasicHeader`]. Using
+/// a header without the length is a compile error:
+///
+/// ```compile_fail
+/// use aversion::util::framed::FramedData;
+/// use aversion::util::postcard::Postcard;
+/// use aversion::util::TinyHeader;
+///
+/// let sink = FramedData::<Postcard, TinyHeader, _>::new(Vec::<u8>::new());
+/// ```
+#[derive(Debug)]
+pub struct Postcard;
+
+impl Format for Postcard {
+    type Error = PostcardDataError;
+
+    fn serialize<T>(msg: &T) -> Result<Vec<u8>, PostcardDataError>
+    where
+        T: Serialize,
+    {
+        Ok(::postcard::to_allocvec(msg)?)
+    }
+
+    fn deserialize_from<T>(r: &mut impl Read) -> Result<T, PostcardDataError>
+    where
+        T: DeserializeOwned,
+    {
+        // postcard deserializes from a buffer, so read the whole message first.
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        Ok(::postcard::from_bytes(&buf)?)
+    }
+}
+
+/// A [`DataSource`] and/or [`DataSink`] using the postcard serialization format.
+///
+/// [`PostcardData`] works with any type that implements [`Read`] or [`Write`].
+/// That includes files, network sockets, and memory buffers.
+///
+/// It implements the [`DataSource`] trait if the inner type implements [`Read`],
+/// and implements the [`DataSink`] trait if the inner type implements [`Write`].
+///
+/// Messages are framed with a [`BasicHeader`], the same as [`CborData`].
+/// Because postcard is not self-describing, the reader and writer must
+/// agree on the exact layout of each message version.
+///
+/// To use a different header type, use `FramedData<Postcard, H, RW>` instead.
+///
+/// [`DataSource`]: crate::group::DataSource
+/// [`DataSink`]: crate::group::DataSink
+/// [`Read`]: std::io::Read
+/// [`Write`]: std::io::Write
+/// [`CborData`]: crate::util::cbor::CborData
+///
+pub type PostcardData<RW> = FramedData<Postcard, BasicHeader, RW>;
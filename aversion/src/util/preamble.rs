@@ -0,0 +1,310 @@
+//! Provides a file preamble, and wrappers that write and validate it.
+//!
+//! A [`Preamble`] is written once, at the start of a file or stream,
+//! before any messages. It identifies the data as an `aversion` message
+//! stream, and records the framing version, the header type, and an
+//! application-defined tag.
+//!
+//! [`PreambleSink`] writes the preamble, and [`PreambleSource`] checks
+//! it before any messages are read.
+//!
+//! ```
+//! use aversion::util::cbor::Cbor;
+//! use aversion::util::preamble::{PreambleError, PreambleSink, PreambleSource};
+//! use aversion::util::BasicHeader;
+//! # use aversion::group::{DataSink, DataSourceExt};
+//! # use aversion::{assign_message_ids, UpgradeLatest, Versioned};
+//! # use serde::{Deserialize, Serialize};
+//! # #[derive(Versioned, UpgradeLatest, Serialize, Deserialize)]
+//! # struct FooV1 { foo: u32 }
+//! # type Foo = FooV1;
+//! # assign_message_ids! { Foo: 1 }
+//!
+//! const MY_APP: u32 = 0x1234;
+//!
+//! let mut sink = PreambleSink::<Cbor, BasicHeader, _>::new(Vec::new(), MY_APP).unwrap();
+//! sink.write_message(&Foo { foo: 1 }).unwrap();
+//! let buf = sink.into_inner().into_inner();
+//!
+//! let mut source = PreambleSource::<Cbor, BasicHeader, _>::new(buf.as_slice(), MY_APP).unwrap();
+//! let _foo: Foo = source.expect_message().unwrap();
+//!
+//! let result = PreambleSource::<Cbor, BasicHeader, _>::new(buf.as_slice(), 0x5678);
+//! assert!(matches!(result, Err(PreambleError::AppTag { .. })));
+//! ```
+
+use crate::group::{DataSink, DataSource, PeekHeader};
+use crate::util::framed::{Format, FramedData, MessageLimits};
+use crate::util::io::{read_u16, read_u32};
+use crate::util::FramedHeader;
+use crate::{MessageId, Versioned};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{self, Read, Write};
+use thiserror::Error;
+
+/// Errors that may occur while reading a [`Preamble`].
+#[derive(Debug, Error)]
+pub enum PreambleError {
+    /// A `std::io::Error` occurred while reading the preamble.
+    #[error("IO Error")]
+    Io(#[from] io::Error),
+    /// The data doesn't start with the preamble magic number.
+    #[error("Missing preamble magic number")]
+    Magic,
+    /// The preamble has a framing version this crate doesn't support.
+    #[error("Unsupported framing version {0}")]
+    Version(u16),
+    /// The data was written with a different header type.
+    #[error("Wrong header kind: expected {expected}, found {found}")]
+    HeaderKind {
+        /// The `HEADER_KIND` of the header type used for reading.
+        expected: u16,
+        /// The header kind stored in the preamble.
+        found: u16,
+    },
+    /// The data was written with a different application tag.
+    #[error("Wrong application tag: expected {expected:#x}, found {found:#x}")]
+    AppTag {
+        /// The application tag used for reading.
+        expected: u32,
+        /// The application tag stored in the preamble.
+        found: u32,
+    },
+}
+
+/// A preamble, written once at the start of a message stream.
+///
+/// The preamble serializes to 16 bytes: the [`MAGIC`][Self::MAGIC]
+/// number, followed by the framing version, the header kind, and the
+/// application tag (in big-endian byte order).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preamble {
+    /// The version of the message framing.
+    pub framing_version: u16,
+    /// The [`HEADER_KIND`] of the message headers.
+    ///
+    /// [`HEADER_KIND`]: crate::util::FramedHeader::HEADER_KIND
+    pub header_kind: u16,
+    /// An application-defined value, identifying the kind of data.
+    pub app_tag: u32,
+}
+
+impl Preamble {
+    /// The magic number at the start of every preamble.
+    pub const MAGIC: [u8; 8] = *b"AVERSION";
+
+    /// The framing version written by this crate.
+    pub const FRAMING_VERSION: u16 = 1;
+
+    /// Create a new `Preamble` for a header type.
+    pub fn for_header<H>(app_tag: u32) -> Self
+    where
+        H: FramedHeader,
+    {
+        Preamble {
+            framing_version: Self::FRAMING_VERSION,
+            header_kind: H::HEADER_KIND,
+            app_tag,
+        }
+    }
+
+    /// Deserialize a preamble from a `Read` stream.
+    ///
+    /// This only checks the magic number; use [`validate`][Self::validate]
+    /// to check the other fields.
+    pub fn deserialize_from(r: &mut impl Read) -> Result<Self, PreambleError> {
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if magic != Self::MAGIC {
+            return Err(PreambleError::Magic);
+        }
+        let framing_version = read_u16(r)?;
+        let header_kind = read_u16(r)?;
+        let app_tag = read_u32(r)?;
+        Ok(Preamble {
+            framing_version,
+            header_kind,
+            app_tag,
+        })
+    }
+
+    /// Serialize a preamble into a `Write` stream.
+    pub fn serialize_into(&self, w: &mut impl Write) -> Result<(), io::Error> {
+        w.write_all(&Self::MAGIC)?;
+        w.write_all(&self.framing_version.to_be_bytes())?;
+        w.write_all(&self.header_kind.to_be_bytes())?;
+        w.write_all(&self.app_tag.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Check that a preamble matches the expected one.
+    pub fn validate(&self, expected: &Preamble) -> Result<(), PreambleError> {
+        if self.framing_version != expected.framing_version {
+            return Err(PreambleError::Version(self.framing_version));
+        }
+        if self.header_kind != expected.header_kind {
+            return Err(PreambleError::HeaderKind {
+                expected: expected.header_kind,
+                found: self.header_kind,
+            });
+        }
+        if self.app_tag != expected.app_tag {
+            return Err(PreambleError::AppTag {
+                expected: expected.app_tag,
+                found: self.app_tag,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A [`DataSink`] that writes a [`Preamble`] before any messages.
+///
+/// The preamble is written when the `PreambleSink` is created;
+/// after that, messages are written by the inner [`FramedData`].
+///
+pub struct PreambleSink<F, H, W> {
+    inner: FramedData<F, H, W>,
+}
+
+impl<F, H, W> PreambleSink<F, H, W>
+where
+    F: Format,
+    H: FramedHeader,
+    W: Write,
+{
+    /// Create a new `PreambleSink`, and write the preamble.
+    pub fn new(mut inner: W, app_tag: u32) -> Result<Self, io::Error> {
+        Preamble::for_header::<H>(app_tag).serialize_into(&mut inner)?;
+        Ok(PreambleSink {
+            inner: FramedData::new(inner),
+        })
+    }
+}
+
+impl<F, H, W> PreambleSink<F, H, W> {
+    /// Set limits on the length of messages.
+    ///
+    /// See [`FramedData::with_limits`].
+    pub fn with_limits(mut self, limits: MessageLimits) -> Self {
+        self.inner = self.inner.with_limits(limits);
+        self
+    }
+
+    /// Consume the `PreambleSink`, returning the inner `FramedData`.
+    pub fn into_inner(self) -> FramedData<F, H, W> {
+        self.inner
+    }
+}
+
+impl<F, H, W> DataSink for PreambleSink<F, H, W>
+where
+    F: Format,
+    H: FramedHeader,
+    W: Write,
+{
+    type Error = F::Error;
+
+    fn write_message<T>(&mut self, msg: &T) -> Result<(), F::Error>
+    where
+        T: Serialize + Versioned,
+        T::Base: MessageId,
+    {
+        self.inner.write_message(msg)
+    }
+}
+
+/// A [`DataSource`] that checks the [`Preamble`] before any messages.
+///
+/// The preamble is read and validated when the `PreambleSource` is
+/// created; after that, messages are read by the inner [`FramedData`].
+///
+pub struct PreambleSource<F, H, R> {
+    inner: FramedData<F, H, R>,
+    preamble: Preamble,
+}
+
+impl<F, H, R> PreambleSource<F, H, R>
+where
+    F: Format,
+    H: FramedHeader,
+    R: Read,
+{
+    /// Create a new `PreambleSource`, and validate the preamble.
+    ///
+    /// The preamble must have the same framing version and header kind
+    /// that a [`PreambleSink`] would write, and the same application tag.
+    pub fn new(mut inner: R, app_tag: u32) -> Result<Self, PreambleError> {
+        let preamble = Preamble::deserialize_from(&mut inner)?;
+        preamble.validate(&Preamble::for_header::<H>(app_tag))?;
+        Ok(PreambleSource {
+            inner: FramedData::new(inner),
+            preamble,
+        })
+    }
+}
+
+impl<F, H, R> PreambleSource<F, H, R> {
+    /// Set limits on the length of messages.
+    ///
+    /// See [`FramedData::with_limits`].
+    pub fn with_limits(mut self, limits: MessageLimits) -> Self {
+        self.inner = self.inner.with_limits(limits);
+        self
+    }
+
+    /// The preamble that was read.
+    pub fn preamble(&self) -> &Preamble {
+        &self.preamble
+    }
+
+    /// Consume the `PreambleSource`, returning the inner `FramedData`.
+    pub fn into_inner(self) -> FramedData<F, H, R> {
+        self.inner
+    }
+}
+
+impl<F, H, R> DataSource for PreambleSource<F, H, R>
+where
+    F: Format,
+    H: FramedHeader,
+    R: Read,
+{
+    type Error = F::Error;
+    type Header = H;
+
+    fn read_header(&mut self) -> Result<H, F::Error> {
+        self.inner.read_header()
+    }
+
+    fn read_message<T>(&mut self, header: &H) -> Result<T, F::Error>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.read_message(header)
+    }
+
+    fn read_raw_message(&mut self, header: &H) -> Result<Vec<u8>, F::Error> {
+        self.inner.read_raw_message(header)
+    }
+
+    fn skip_message(&mut self, header: &H) -> Result<(), F::Error> {
+        self.inner.skip_message(header)
+    }
+
+    fn at_eof(&mut self) -> Result<bool, F::Error> {
+        self.inner.at_eof()
+    }
+}
+
+impl<F, H, R> PeekHeader for PreambleSource<F, H, R>
+where
+    F: Format,
+    H: FramedHeader,
+    R: Read,
+{
+    fn peek_header(&mut self) -> Result<&H, F::Error> {
+        self.inner.peek_header()
+    }
+}
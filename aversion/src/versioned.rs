@@ -1,3 +1,5 @@
+use core::convert::Infallible;
+
 /// A data structure that has a version number.
 ///
 /// A `Versioned` data structure is part of a family of data structures,
@@ -74,9 +76,100 @@ where
     }
 }
 
+/// Attempt to convert an older message version to a newer message version.
+///
+/// This is the fallible version of [`FromVersion`], for upgrades that may
+/// fail (e.g. narrowing a numeric field, or parsing a string into an enum).
+///
+/// Like the standard library's `TryFrom` and `TryInto` traits, users should
+/// implement `TryFromVersion`, and the corresponding [`TryIntoVersion`]
+/// implementation is provided by a blanket implementation. Any type that
+/// implements [`FromVersion`] also implements `TryFromVersion`, with an
+/// error type of [`Infallible`].
+///
+pub trait TryFromVersion<T>: Versioned + Sized
+where
+    T: Versioned,
+{
+    /// The type returned in the event of a conversion error.
+    type Error;
+
+    /// Attempt to convert from an older `Versioned` type to a newer `Versioned` type.
+    fn try_from_version(t: T) -> Result<Self, Self::Error>;
+}
+
+// Like std::convert::TryFrom, infallible conversions are
+// semantically equivalent to fallible conversions with an
+// uninhabited error type.
+impl<T, U> TryFromVersion<U> for T
+where
+    T: Versioned,
+    U: Versioned + IntoVersion<T>,
+{
+    type Error = Infallible;
+
+    fn try_from_version(u: U) -> Result<Self, Self::Error> {
+        Ok(u.into_version())
+    }
+}
+
+/// Attempt to convert an older message version to a newer message version.
+///
+/// This is the inverse of [`TryFromVersion`]; see its documentation for more.
+pub trait TryIntoVersion<T> {
+    /// The type returned in the event of a conversion error.
+    type Error;
+
+    /// Attempt to convert from an older `Versioned` type to a newer `Versioned` type.
+    fn try_into_version(self) -> Result<T, Self::Error>;
+}
+
+// Like std::convert::TryInto, provide a blanket implementation
+// so that TryFrom<T> for U implies TryInto<U> for T
+impl<T, U> TryIntoVersion<U> for T
+where
+    T: Versioned,
+    U: TryFromVersion<T>,
+{
+    type Error = U::Error;
+
+    fn try_into_version(self) -> Result<U, U::Error> {
+        U::try_from_version(self)
+    }
+}
+
+/// Convert a newer message version to an older message version.
+///
+/// This is the opposite of [`FromVersion`], and is useful when writing
+/// messages to peers that only understand an older version.
+///
+/// Users should implement `DowngradeTo` for each pair of versions `N+1`
+/// and `N`. The `DowngradeTo` derive macro can then be used on the latest
+/// version to chain those implementations, so that the latest version
+/// can be downgraded to any older version.
+///
+pub trait DowngradeTo<T>: Versioned
+where
+    T: Versioned,
+{
+    /// Convert from a newer `Versioned` type to an older `Versioned` type.
+    fn downgrade_to(&self) -> T;
+}
+
+// Like FromVersion, DowngradeTo should be reflexive.
+impl<T> DowngradeTo<T> for T
+where
+    T: Versioned + Clone,
+{
+    fn downgrade_to(&self) -> T {
+        self.clone()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::convert::TryInto;
 
     #[derive(Debug, Clone)]
     struct MyStructV1 {
@@ -137,6 +230,71 @@ mod tests {
         }
     }
 
+    #[derive(Debug, PartialEq)]
+    struct NarrowStructV1 {
+        x: u64,
+    }
+
+    impl Versioned for NarrowStructV1 {
+        const VER: u16 = 1;
+        type Base = NarrowStruct;
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct NarrowStructV2 {
+        x: u8,
+    }
+
+    type NarrowStruct = NarrowStructV2;
+
+    impl Versioned for NarrowStructV2 {
+        const VER: u16 = 2;
+        type Base = NarrowStruct;
+    }
+
+    impl TryFromVersion<NarrowStructV1> for NarrowStructV2 {
+        type Error = std::num::TryFromIntError;
+
+        fn try_from_version(nsv1: NarrowStructV1) -> Result<Self, Self::Error> {
+            Ok(Self {
+                x: nsv1.x.try_into()?,
+            })
+        }
+    }
+
+    #[test]
+    fn test_try_adapter() {
+        let x = NarrowStructV1 { x: 42 };
+        let y: NarrowStructV2 = x.try_into_version().unwrap();
+        assert_eq!(y, NarrowStructV2 { x: 42 });
+
+        let x = NarrowStructV1 { x: 4242 };
+        NarrowStructV2::try_from_version(x).unwrap_err();
+
+        // Infallible upgrades also work.
+        let x = MyStructV1 { x: 42 };
+        let z = MyStructV3::try_from_version(x).unwrap();
+        assert_eq!(z, MyStructV3 { x: 42, y: 0 })
+    }
+
+    impl DowngradeTo<MyStructV2> for MyStructV3 {
+        fn downgrade_to(&self) -> MyStructV2 {
+            MyStructV2 {
+                x: self.x,
+                y: self.y.try_into().unwrap_or(u8::MAX),
+            }
+        }
+    }
+
+    #[test]
+    fn test_downgrade() {
+        let x = MyStructV3 { x: 42, y: 1000 };
+        let y: MyStructV2 = x.downgrade_to();
+        assert_eq!((y.x, y.y), (42, 255));
+        let z: MyStructV3 = x.downgrade_to();
+        assert_eq!(z, x);
+    }
+
     #[test]
     fn test_adapter() {
         let x = MyStructV1 { x: 42 };
@@ -0,0 +1,265 @@
+use aversion::group::{
+    AsyncDataSink, AsyncDataSource, AsyncDataSourceExt, AsyncGroupDeserialize, DataSink,
+    UnknownMessage,
+};
+use aversion::util::cbor::{AsyncCborData, Cbor, CborData, CborDataError};
+use aversion::util::framed::{AsyncFramedData, FramedData, MessageLimits};
+use aversion::util::{BasicHeader, ChecksumHeader, FramedHeader, VarintHeader};
+use aversion::{FromVersion, GroupDeserialize, UpgradeLatest, Versioned};
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::pin::pin;
+use tokio::io::duplex;
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize)]
+struct FooV1 {
+    foo: u32,
+}
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize)]
+struct FooV2 {
+    foo2: u32,
+}
+
+impl FromVersion<FooV1> for FooV2 {
+    fn from_version(v1: FooV1) -> Self {
+        Self { foo2: v1.foo + 1 }
+    }
+}
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest)]
+struct FooV3 {
+    foo3: u32,
+}
+
+impl FromVersion<FooV2> for FooV3 {
+    fn from_version(v2: FooV2) -> Self {
+        Self { foo3: v2.foo2 + 10 }
+    }
+}
+
+/// This is the latest version.
+type Foo = FooV3;
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest)]
+struct BarV1 {
+    bar: u64,
+}
+
+/// This is the latest version.
+type Bar = BarV1;
+
+#[derive(Debug, PartialEq, GroupDeserialize)]
+enum MyGroup {
+    #[aversion(id = 123)]
+    Foo(Foo),
+    #[aversion(id = 999)]
+    Bar(Bar),
+}
+
+#[derive(Debug, PartialEq, GroupDeserialize)]
+enum OnlyBar {
+    Bar(Bar),
+    #[aversion(unknown)]
+    Unknown(UnknownMessage),
+}
+
+#[tokio::test]
+async fn test_async_group() {
+    let (client, server) = duplex(1024);
+    let mut out_stream = AsyncCborData::new(client);
+    let mut in_stream = AsyncCborData::new(server);
+
+    out_stream
+        .write_message(&FooV1 { foo: 1234 })
+        .await
+        .unwrap();
+    out_stream.write_message(&Bar { bar: 42 }).await.unwrap();
+    out_stream.write_message(&FooV2 { foo2: 1 }).await.unwrap();
+
+    let message = MyGroup::read_message_async(&mut in_stream).await.unwrap();
+    assert_eq!(message, MyGroup::Foo(Foo { foo3: 1245 }));
+    let message = MyGroup::read_message_async(&mut in_stream).await.unwrap();
+    assert_eq!(message, MyGroup::Bar(Bar { bar: 42 }));
+
+    // Read a message without knowing its type in advance.
+    let header = in_stream.read_header().await.unwrap();
+    assert_eq!(header.msg_ver, 2);
+    let message: FooV2 = in_stream.read_message(&header).await.unwrap();
+    assert_eq!(message, FooV2 { foo2: 1 });
+}
+
+#[tokio::test]
+async fn test_async_unknown() {
+    let (client, server) = duplex(1024);
+    let mut out_stream = AsyncCborData::new(client);
+    let mut in_stream = AsyncCborData::new(server);
+
+    out_stream.write_message(&Foo { foo3: 5 }).await.unwrap();
+    out_stream.write_message(&Bar { bar: 6 }).await.unwrap();
+
+    let message = OnlyBar::read_message_async(&mut in_stream).await.unwrap();
+    assert!(matches!(
+        message,
+        OnlyBar::Unknown(UnknownMessage { msg_id: 123, .. })
+    ));
+    let message = OnlyBar::read_message_async(&mut in_stream).await.unwrap();
+    assert_eq!(message, OnlyBar::Bar(Bar { bar: 6 }));
+}
+
+#[tokio::test]
+async fn test_sync_async_compatible() {
+    // Data written by CborData can be read by AsyncCborData.
+    let mut out_stream = CborData::new(Cursor::new(Vec::<u8>::new()));
+    out_stream.write_message(&FooV1 { foo: 99 }).unwrap();
+    let buf = out_stream.into_inner().into_inner();
+
+    let mut in_stream = AsyncCborData::new(buf.as_slice());
+    let message = MyGroup::read_message_async(&mut in_stream).await.unwrap();
+    assert_eq!(message, MyGroup::Foo(Foo { foo3: 110 }));
+}
+
+#[derive(Debug, PartialEq, GroupDeserialize)]
+#[aversion(skip_unknown)]
+enum SkipToBar {
+    Bar(Bar),
+}
+
+#[tokio::test]
+async fn test_async_stream() {
+    let (client, server) = duplex(1024);
+    let mut out_stream = AsyncCborData::new(client);
+    let mut in_stream = AsyncCborData::new(server);
+
+    out_stream.write_message(&Bar { bar: 1 }).await.unwrap();
+    out_stream.write_message(&FooV2 { foo2: 2 }).await.unwrap();
+    // Drop the writer, so the reader sees EOF.
+    drop(out_stream);
+
+    let messages: Vec<MyGroup> = in_stream.messages().try_collect().await.unwrap();
+    assert_eq!(
+        messages,
+        vec![MyGroup::Bar(Bar { bar: 1 }), MyGroup::Foo(Foo { foo3: 12 })]
+    );
+}
+
+#[tokio::test]
+async fn test_async_stream_errors() {
+    let (client, server) = duplex(1024);
+    let mut out_stream = AsyncCborData::new(client);
+    let mut in_stream = AsyncCborData::new(server);
+
+    // Skipped messages at the end of the data are a clean end.
+    out_stream.write_message(&Bar { bar: 1 }).await.unwrap();
+    out_stream.write_message(&FooV1 { foo: 2 }).await.unwrap();
+    drop(out_stream);
+    let messages: Vec<SkipToBar> = in_stream.messages().try_collect().await.unwrap();
+    assert_eq!(messages, vec![SkipToBar::Bar(Bar { bar: 1 })]);
+
+    // A partial header is an error, and ends the stream.
+    let buf: &[u8] = &[0, 1, 0];
+    let mut in_stream = AsyncCborData::new(buf);
+    let mut messages = pin!(in_stream.messages::<MyGroup>());
+    let err = messages.next().await.unwrap().unwrap_err();
+    assert!(matches!(err, CborDataError::Eof));
+    assert!(messages.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_async_limits() {
+    let limits = MessageLimits::new().with_max_len_for(999, 1);
+    let mut out_stream = AsyncCborData::new(Vec::new()).with_limits(limits.clone());
+    let err = out_stream
+        .write_message(&Bar { bar: 1000 })
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        CborDataError::TooLarge {
+            msg_id: 999,
+            max_len: 1
+        }
+    ));
+
+    let buf = BasicHeader::new(999, 1, u32::MAX).serialize();
+    let mut in_stream = AsyncCborData::new(buf.as_ref()).with_limits(limits.clone());
+    let err = MyGroup::read_message_async(&mut in_stream)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        CborDataError::TooLarge {
+            msg_id: 999,
+            max_len: 1
+        }
+    ));
+
+    // The header isn't lost if `at_eof` reads it.
+    let mut in_stream = AsyncCborData::new(buf.as_ref()).with_limits(limits);
+    assert!(!in_stream.at_eof().await.unwrap());
+    let err = in_stream.read_header().await.unwrap_err();
+    assert!(matches!(err, CborDataError::TooLarge { msg_id: 999, .. }));
+
+    // There is a limit by default.
+    let mut in_stream = AsyncCborData::new(buf.as_ref());
+    let err = in_stream.read_header().await.unwrap_err();
+    assert!(matches!(
+        err,
+        CborDataError::TooLarge {
+            msg_id: 999,
+            max_len: MessageLimits::DEFAULT_MAX_LEN
+        }
+    ));
+}
+
+#[tokio::test]
+async fn test_async_trailing_data() {
+    // A message with extra bytes after it, followed by a valid message.
+    let mut msg_buf = serde_cbor::to_vec(&Bar { bar: 1 }).unwrap();
+    msg_buf.extend_from_slice(&[1, 2, 3]);
+    let mut buf = BasicHeader::new(999, 1, msg_buf.len() as u32)
+        .serialize()
+        .to_vec();
+    buf.extend_from_slice(&msg_buf);
+    let mut out_stream = CborData::new(&mut buf);
+    out_stream.write_message(&Bar { bar: 2 }).unwrap();
+
+    let mut in_stream = AsyncCborData::new(buf.as_slice());
+    let err = MyGroup::read_message_async(&mut in_stream)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, CborDataError::Decode { id: 999, .. }));
+    let message = MyGroup::read_message_async(&mut in_stream).await.unwrap();
+    assert_eq!(message, MyGroup::Bar(Bar { bar: 2 }));
+    assert!(in_stream.at_eof().await.unwrap());
+}
+
+async fn check_header_type<H>()
+where
+    H: FramedHeader + Send + Sync,
+{
+    // Data written by FramedData can be read by AsyncFramedData, and
+    // vice versa.
+    let mut out_stream = FramedData::<Cbor, H, _>::new(Vec::new());
+    out_stream.write_message(&FooV1 { foo: 7 }).unwrap();
+    let buf = out_stream.into_inner();
+    let mut in_stream = AsyncFramedData::<Cbor, H, _>::new(buf.as_slice());
+    let message = MyGroup::read_message_async(&mut in_stream).await.unwrap();
+    assert_eq!(message, MyGroup::Foo(Foo { foo3: 18 }));
+    assert!(in_stream.at_eof().await.unwrap());
+
+    let mut out_stream = AsyncFramedData::<Cbor, H, _>::new(Vec::new());
+    out_stream.write_message(&Bar { bar: 8 }).await.unwrap();
+    let buf = out_stream.into_inner();
+    let mut in_stream = FramedData::<Cbor, H, _>::new(buf.as_slice());
+    let message = MyGroup::read_message(&mut in_stream).unwrap();
+    assert_eq!(message, MyGroup::Bar(Bar { bar: 8 }));
+}
+
+#[tokio::test]
+async fn test_async_header_types() {
+    check_header_type::<BasicHeader>().await;
+    check_header_type::<VarintHeader>().await;
+    check_header_type::<ChecksumHeader>().await;
+}
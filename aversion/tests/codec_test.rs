@@ -0,0 +1,227 @@
+use aversion::group::UnknownMessage;
+use aversion::util::cbor::CborDataError;
+use aversion::util::codec::AversionCodec;
+use aversion::util::framed::MessageLimits;
+use aversion::util::BasicHeader;
+use aversion::{FromVersion, GroupDeserialize, UpgradeLatest, Versioned};
+use bytes::BytesMut;
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::io::duplex;
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize)]
+struct FooV1 {
+    foo: u32,
+}
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest)]
+struct FooV2 {
+    foo2: u32,
+}
+
+impl FromVersion<FooV1> for FooV2 {
+    fn from_version(v1: FooV1) -> Self {
+        Self { foo2: v1.foo + 1 }
+    }
+}
+
+/// This is the latest version.
+type Foo = FooV2;
+
+#[derive(Debug, Clone, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest)]
+struct BarV1 {
+    bar: String,
+}
+
+/// This is the latest version.
+type Bar = BarV1;
+
+#[derive(Debug, PartialEq, GroupDeserialize)]
+enum MyProto {
+    #[aversion(id = 10)]
+    Foo(Foo),
+    #[aversion(id = 11)]
+    Bar(Bar),
+}
+
+#[derive(Debug, PartialEq, GroupDeserialize)]
+#[aversion(skip_unknown)]
+enum OnlyBar {
+    Bar(Bar),
+}
+
+#[tokio::test]
+async fn test_framed() {
+    let (client, server) = duplex(64);
+    let mut client = Framed::new(client, AversionCodec::<MyProto>::new());
+    let mut server = Framed::new(server, AversionCodec::<MyProto>::new());
+
+    let bar = Bar {
+        bar: "a string that is longer than the duplex buffer size".repeat(4),
+    };
+    let writer = async {
+        client.send(&FooV1 { foo: 7 }).await.unwrap();
+        client.send(&bar).await.unwrap();
+        client.send(&Foo { foo2: 99 }).await.unwrap();
+    };
+    let reader = async {
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            received.push(server.next().await.unwrap().unwrap());
+        }
+        received
+    };
+    let ((), received) = tokio::join!(writer, reader);
+
+    assert_eq!(
+        received,
+        vec![
+            MyProto::Foo(Foo { foo2: 8 }),
+            MyProto::Bar(bar.clone()),
+            MyProto::Foo(Foo { foo2: 99 }),
+        ]
+    );
+}
+
+#[test]
+fn test_partial_frames() {
+    let mut codec = AversionCodec::<MyProto>::new();
+    let mut encoded = BytesMut::new();
+    codec.encode(&FooV1 { foo: 1 }, &mut encoded).unwrap();
+    codec.encode(&Foo { foo2: 5 }, &mut encoded).unwrap();
+
+    // Feed the decoder one byte at a time.
+    let mut src = BytesMut::new();
+    let mut received = Vec::new();
+    for byte in encoded {
+        src.extend_from_slice(&[byte]);
+        if let Some(message) = codec.decode(&mut src).unwrap() {
+            received.push(message);
+        }
+    }
+    assert!(src.is_empty());
+    assert_eq!(
+        received,
+        vec![MyProto::Foo(Foo { foo2: 2 }), MyProto::Foo(Foo { foo2: 5 })]
+    );
+}
+
+#[test]
+fn test_skip_unknown() {
+    let mut codec = AversionCodec::<OnlyBar>::new();
+    let mut src = BytesMut::new();
+    codec.encode(&Foo { foo2: 1 }, &mut src).unwrap();
+    codec.encode(&Foo { foo2: 2 }, &mut src).unwrap();
+    let bar = Bar { bar: "bar".into() };
+    codec.encode(&bar, &mut src).unwrap();
+
+    let message = codec.decode(&mut src).unwrap();
+    assert_eq!(message, Some(OnlyBar::Bar(bar)));
+    assert!(src.is_empty());
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+}
+
+#[test]
+fn test_corrupt_after_skip() {
+    let mut codec = AversionCodec::<OnlyBar>::new();
+    let mut src = BytesMut::new();
+    codec.encode(&Foo { foo2: 1 }, &mut src).unwrap();
+
+    // A frame with a valid header, whose body isn't valid CBOR.
+    let mut corrupt = BytesMut::new();
+    codec
+        .encode(&Bar { bar: "bar".into() }, &mut corrupt)
+        .unwrap();
+    for byte in &mut corrupt[8..] {
+        *byte = 0xff;
+    }
+    src.extend_from_slice(&corrupt);
+
+    // The first message is skipped, but the error isn't.
+    let err = codec.decode(&mut src).unwrap_err();
+    assert!(
+        matches!(err, CborDataError::Decode { id: 11, .. }),
+        "{:?}",
+        err
+    );
+}
+
+#[test]
+fn test_unknown_variant() {
+    #[derive(Debug, GroupDeserialize)]
+    enum Unknowns {
+        Bar(Bar),
+        #[aversion(unknown)]
+        Unknown(UnknownMessage),
+    }
+
+    let mut codec = AversionCodec::<Unknowns>::new();
+    let mut src = BytesMut::new();
+    codec.encode(&Foo { foo2: 1 }, &mut src).unwrap();
+    let bar = Bar { bar: "bar".into() };
+    codec.encode(&bar, &mut src).unwrap();
+
+    let message = codec.decode(&mut src).unwrap().unwrap();
+    assert!(matches!(
+        message,
+        Unknowns::Unknown(UnknownMessage { msg_id: 10, .. })
+    ));
+    let message = codec.decode(&mut src).unwrap().unwrap();
+    assert!(matches!(message, Unknowns::Bar(b) if b == bar));
+}
+
+#[test]
+fn test_limits() {
+    let limits = MessageLimits::new().with_max_len(16);
+    let mut codec = AversionCodec::<MyProto>::new().with_limits(limits);
+    let mut src = BytesMut::new();
+    let err = codec
+        .encode(
+            &Bar {
+                bar: "a string longer than 16 bytes".into(),
+            },
+            &mut src,
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        CborDataError::TooLarge {
+            msg_id: 11,
+            max_len: 16
+        }
+    ));
+    assert!(src.is_empty());
+
+    // A hostile header is rejected before the decoder waits for the message.
+    src.extend_from_slice(&BasicHeader::new(10, 2, u32::MAX).serialize());
+    let err = codec.decode(&mut src).unwrap_err();
+    assert!(matches!(
+        err,
+        CborDataError::TooLarge {
+            msg_id: 10,
+            max_len: 16
+        }
+    ));
+
+    // There is a limit by default.
+    let mut codec = AversionCodec::<MyProto>::new();
+    let mut src = BytesMut::new();
+    src.extend_from_slice(&BasicHeader::new(10, 2, u32::MAX).serialize());
+    let err = codec.decode(&mut src).unwrap_err();
+    assert!(matches!(
+        err,
+        CborDataError::TooLarge {
+            msg_id: 10,
+            max_len: MessageLimits::DEFAULT_MAX_LEN
+        }
+    ));
+
+    // Without a limit, the decoder doesn't reserve space for the whole
+    // message before it arrives.
+    let mut codec = AversionCodec::<MyProto>::new().with_limits(MessageLimits::unlimited());
+    let mut src = BytesMut::new();
+    src.extend_from_slice(&BasicHeader::new(10, 2, u32::MAX).serialize());
+    assert!(codec.decode(&mut src).unwrap().is_none());
+    assert!(src.capacity() < 1024 * 1024);
+}
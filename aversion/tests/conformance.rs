@@ -0,0 +1,344 @@
+//! Tests that every bundled `DataSource`/`DataSink` must pass.
+//!
+//! Each format gets its own module, generated by `conformance_tests!`,
+//! which is only built if that format's feature is enabled.
+
+#![allow(dead_code, unused_imports, unused_macros)]
+
+use aversion::group::{DataSink, DataSource, DataSourceExt, PeekHeader, UnknownMessage};
+use aversion::{
+    assign_message_ids, FromVersion, GroupDeserialize, GroupSerialize, MessageId, UpgradeLatest,
+    Versioned,
+};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize)]
+struct FooV1 {
+    foo: u32,
+}
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize)]
+struct FooV2 {
+    foo2: u32,
+}
+
+impl FromVersion<FooV1> for FooV2 {
+    fn from_version(v1: FooV1) -> Self {
+        Self { foo2: v1.foo + 1 }
+    }
+}
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest)]
+struct FooV3 {
+    foo3: u64,
+    name: String,
+}
+
+impl FromVersion<FooV2> for FooV3 {
+    fn from_version(v2: FooV2) -> Self {
+        Self {
+            foo3: (v2.foo2 + 10).into(),
+            name: "upgraded".to_owned(),
+        }
+    }
+}
+
+/// This is the latest version.
+type Foo = FooV3;
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest)]
+struct BarV1 {
+    bar: Vec<i16>,
+    flag: Option<bool>,
+}
+
+/// This is the latest version.
+type Bar = BarV1;
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest)]
+struct BazV1 {
+    baz: (u8, char),
+}
+
+/// This is the latest version.
+type Baz = BazV1;
+
+assign_message_ids! {
+    Foo: 123,
+    Bar: 999,
+    Baz: 456,
+}
+
+#[derive(Debug, PartialEq, GroupDeserialize, GroupSerialize)]
+enum MyGroup {
+    Foo(Foo),
+    Bar(Bar),
+    Baz(Baz),
+}
+
+/// A group that doesn't handle unknown messages.
+#[derive(Debug, PartialEq, GroupDeserialize)]
+enum FooOrBar {
+    Foo(Foo),
+    Bar(Bar),
+}
+
+/// A group that returns unknown messages to the caller.
+#[derive(Debug, PartialEq, GroupDeserialize)]
+enum OnlyFoo {
+    Foo(Foo),
+    #[aversion(unknown)]
+    Unknown(UnknownMessage),
+}
+
+/// A group that skips over unknown messages.
+#[derive(Debug, PartialEq, GroupDeserialize)]
+#[aversion(skip_unknown)]
+enum OnlyBaz {
+    Baz(Baz),
+}
+
+fn sample_messages() -> Vec<MyGroup> {
+    vec![
+        MyGroup::Foo(Foo {
+            foo3: 1 << 40,
+            name: "foo".to_owned(),
+        }),
+        MyGroup::Bar(Bar {
+            bar: vec![-1, 0, 1000],
+            flag: Some(false),
+        }),
+        MyGroup::Baz(Baz { baz: (7, 'z') }),
+        MyGroup::Bar(Bar {
+            bar: vec![],
+            flag: None,
+        }),
+    ]
+}
+
+/// Format an error, followed by all of its sources.
+fn error_chain(err: &dyn Error) -> String {
+    let mut text = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        text = format!("{}: {}", text, err);
+        source = err.source();
+    }
+    text
+}
+
+/// Generate the conformance tests for one format.
+///
+/// `$data` is the data source/sink type, which must be constructed with
+/// `$data::new`, writing to a `Vec<u8>` and reading from a `&[u8]`.
+/// Line-based text formats should add `text`, which allows the final
+/// newline to be missing.
+macro_rules! conformance_tests {
+    ($name:ident, $data:ident) => {
+        conformance_tests!($name, $data, false);
+    };
+    ($name:ident, $data:ident, text) => {
+        conformance_tests!($name, $data, true);
+    };
+    ($name:ident, $data:ident, $text:expr) => {
+        mod $name {
+            use super::*;
+
+            const TEXT: bool = $text;
+
+            /// Write some messages, returning the serialized data.
+            fn write(f: impl FnOnce(&mut $data<Vec<u8>>)) -> Vec<u8> {
+                let mut sink = $data::new(Vec::new());
+                f(&mut sink);
+                sink.into_inner()
+            }
+
+            #[test]
+            fn round_trip() {
+                let messages = sample_messages();
+                let buf = write(|sink| {
+                    for message in &messages {
+                        message.write_message(sink).unwrap();
+                    }
+                });
+                let mut src = $data::new(buf.as_slice());
+                for expected in &messages {
+                    let message = MyGroup::read_message(&mut src).unwrap();
+                    assert_eq!(&message, expected);
+                }
+                assert!(src.at_eof().unwrap());
+            }
+
+            #[test]
+            fn upgrade() {
+                let buf = write(|sink| {
+                    sink.write_message(&FooV1 { foo: 1 }).unwrap();
+                    sink.write_message(&FooV2 { foo2: 2 }).unwrap();
+                });
+                let mut src = $data::new(buf.as_slice());
+                let message = MyGroup::read_message(&mut src).unwrap();
+                assert_eq!(
+                    message,
+                    MyGroup::Foo(Foo {
+                        foo3: 12,
+                        name: "upgraded".to_owned(),
+                    })
+                );
+                let message: Foo = src.expect_message().unwrap();
+                assert_eq!(message.foo3, 12);
+            }
+
+            #[test]
+            fn peek() {
+                let buf = write(|sink| {
+                    sink.write_message(&Baz { baz: (1, 'a') }).unwrap();
+                    sink.write_message(&FooV1 { foo: 1 }).unwrap();
+                });
+                let mut src = $data::new(buf.as_slice());
+                assert!(src.next_is::<Baz>().unwrap());
+                assert!(!src.next_is::<Foo>().unwrap());
+                assert_eq!(src.peek_header().unwrap().msg_id, Baz::MSG_ID);
+                let message = MyGroup::read_message(&mut src).unwrap();
+                assert_eq!(message, MyGroup::Baz(Baz { baz: (1, 'a') }));
+                assert!(src.next_is::<Foo>().unwrap());
+                let header = src.read_header().unwrap();
+                assert_eq!(header.msg_ver, 1);
+                let message: FooV1 = src.read_message(&header).unwrap();
+                assert_eq!(message, FooV1 { foo: 1 });
+            }
+
+            #[test]
+            fn unexpected_message() {
+                let buf = write(|sink| {
+                    sink.write_message(&Baz { baz: (1, 'a') }).unwrap();
+                });
+                let mut src = $data::new(buf.as_slice());
+                let err = src.expect_message::<Foo>().unwrap_err();
+                let text = error_chain(&err);
+                assert!(text.contains("FooV3, got message id 456"), "{}", text);
+            }
+
+            #[test]
+            fn unknown_messages() {
+                let buf = write(|sink| {
+                    sink.write_message(&Baz { baz: (1, 'a') }).unwrap();
+                    sink.write_message(&FooV1 { foo: 1 }).unwrap();
+                    sink.write_message(&Bar {
+                        bar: vec![2],
+                        flag: None,
+                    })
+                    .unwrap();
+                });
+
+                // By default, unknown messages are errors.
+                // The error should include the message id.
+                let mut src = $data::new(buf.as_slice());
+                let err = FooOrBar::read_message(&mut src).unwrap_err();
+                let text = error_chain(&err);
+                assert!(text.contains("Unknown message id 456"), "{}", text);
+
+                // Unknown messages can be captured.
+                let mut src = $data::new(buf.as_slice());
+                let message = OnlyFoo::read_message(&mut src).unwrap();
+                match message {
+                    OnlyFoo::Unknown(unknown) => {
+                        assert_eq!(unknown.msg_id, Baz::MSG_ID);
+                        assert_eq!(unknown.msg_ver, 1);
+                    }
+                    _ => panic!("expected unknown message"),
+                }
+                let message = OnlyFoo::read_message(&mut src).unwrap();
+                assert!(matches!(message, OnlyFoo::Foo(_)));
+                let message = OnlyFoo::read_message(&mut src).unwrap();
+                assert!(matches!(message, OnlyFoo::Unknown(_)));
+
+                // Unknown messages can be skipped, up to the end of the data.
+                let mut src = $data::new(buf.as_slice());
+                let messages: Vec<OnlyBaz> = src.messages().collect::<Result<_, _>>().unwrap();
+                assert_eq!(messages, vec![OnlyBaz::Baz(Baz { baz: (1, 'a') })]);
+            }
+
+            #[test]
+            fn messages_iterator() {
+                let messages = sample_messages();
+                let buf = write(|sink| {
+                    for message in &messages {
+                        message.write_message(sink).unwrap();
+                    }
+                });
+                let mut src = $data::new(buf.as_slice());
+                let received: Vec<MyGroup> = src.messages().collect::<Result<_, _>>().unwrap();
+                assert_eq!(received, messages);
+
+                let mut src = $data::new(&[][..]);
+                assert!(src.messages::<MyGroup>().next().is_none());
+            }
+
+            #[test]
+            fn truncated() {
+                let baz = Baz { baz: (1, 'a') };
+                let foo = Foo {
+                    foo3: 3,
+                    name: "a long string".to_owned(),
+                };
+                let boundary = write(|sink| sink.write_message(&baz).unwrap()).len();
+                let buf = write(|sink| {
+                    sink.write_message(&baz).unwrap();
+                    sink.write_message(&foo).unwrap();
+                });
+                // Stopping anywhere other than a message boundary is an error.
+                let is_boundary = |len: usize| len == boundary || (TEXT && buf[len] == b'\n');
+                for len in (1..buf.len()).filter(|&len| !is_boundary(len)) {
+                    let mut src = $data::new(&buf[..len]);
+                    let results: Vec<_> = src.messages::<MyGroup>().collect();
+                    let last = results.last().expect("truncated data should be an error");
+                    assert!(last.is_err(), "truncated at {}", len);
+                }
+            }
+        }
+    };
+}
+
+#[cfg(feature = "serde_cbor")]
+use aversion::util::cbor::CborData;
+#[cfg(feature = "serde_cbor")]
+conformance_tests!(cbor, CborData);
+
+#[cfg(feature = "bincode")]
+use aversion::util::bincode::BincodeData;
+#[cfg(feature = "bincode")]
+conformance_tests!(bincode, BincodeData);
+
+/// A message that ends early is reported as an EOF, not a format error.
+#[cfg(feature = "bincode")]
+#[test]
+fn bincode_truncated_eof() {
+    use aversion::util::bincode::BincodeDataError;
+
+    let mut sink = BincodeData::new(Vec::new());
+    sink.write_message(&Bar {
+        bar: vec![1, 2],
+        flag: None,
+    })
+    .unwrap();
+    let buf = sink.into_inner();
+    let mut src = BincodeData::new(&buf[..buf.len() - 1]);
+    let err = MyGroup::read_message(&mut src).unwrap_err();
+    assert!(matches!(err, BincodeDataError::Eof));
+}
+
+#[cfg(feature = "rmp-serde")]
+use aversion::util::msgpack::MsgpackData;
+#[cfg(feature = "rmp-serde")]
+conformance_tests!(msgpack, MsgpackData);
+
+#[cfg(feature = "postcard")]
+use aversion::util::postcard::PostcardData;
+#[cfg(feature = "postcard")]
+conformance_tests!(postcard, PostcardData);
+
+#[cfg(feature = "serde_json")]
+use aversion::util::json::JsonLinesData;
+#[cfg(feature = "serde_json")]
+conformance_tests!(json, JsonLinesData, text);
@@ -1,8 +1,20 @@
-use aversion::group::{DataSink, DataSourceExt};
-use aversion::util::cbor::CborData;
-use aversion::{assign_message_ids, FromVersion, GroupDeserialize, UpgradeLatest, Versioned};
+use aversion::group::{DataSink, DataSource, DataSourceExt, PeekHeader, UnknownMessage};
+use aversion::group::{GroupHeader, UpgradeLatest as UpgradeLatestTrait};
+use aversion::util::cbor::{Cbor, CborData, CborDataError};
+use aversion::util::framed::{FramedData, MessageLimits};
+use aversion::util::preamble::{Preamble, PreambleError, PreambleSink, PreambleSource};
+use aversion::util::{
+    BasicHeader, ChecksumHeader, FramedHeader, MarkedHeader, TinyHeader, VarintHeader,
+};
+use aversion::{
+    assign_message_ids, DowngradeTo, FromVersion, GroupDeserialize, GroupSerialize, MessageId,
+    TryFromVersion, UpgradeLatest, Versioned,
+};
 use serde::{Deserialize, Serialize};
-use std::io::{Cursor, Seek, SeekFrom};
+use std::convert::TryInto;
+use std::error::Error;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::num::TryFromIntError;
 
 #[derive(Debug, PartialEq, Versioned, Serialize, Deserialize)]
 struct FooV1 {
@@ -20,11 +32,25 @@ impl FromVersion<FooV1> for FooV2 {
     }
 }
 
-#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest)]
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest, DowngradeTo)]
 struct FooV3 {
     foo3: u32,
 }
 
+impl DowngradeTo<FooV1> for FooV2 {
+    fn downgrade_to(&self) -> FooV1 {
+        FooV1 { foo: self.foo2 - 1 }
+    }
+}
+
+impl DowngradeTo<FooV2> for FooV3 {
+    fn downgrade_to(&self) -> FooV2 {
+        FooV2 {
+            foo2: self.foo3 - 10,
+        }
+    }
+}
+
 impl FromVersion<FooV2> for FooV3 {
     fn from_version(v2: FooV2) -> Self {
         Self { foo3: v2.foo2 + 10 }
@@ -42,7 +68,46 @@ struct BarV1 {
 /// This is the latest version.
 type Bar = BarV1;
 
-#[derive(Debug, PartialEq, GroupDeserialize)]
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize)]
+struct QuxV1 {
+    qux: u64,
+}
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize)]
+struct QuxV2 {
+    qux: u32,
+}
+
+impl TryFromVersion<QuxV1> for QuxV2 {
+    type Error = TryFromIntError;
+
+    fn try_from_version(v1: QuxV1) -> Result<Self, Self::Error> {
+        Ok(Self {
+            qux: v1.qux.try_into()?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest)]
+#[aversion(fallible)]
+struct QuxV3 {
+    qux: u32,
+    extra: bool,
+}
+
+impl FromVersion<QuxV2> for QuxV3 {
+    fn from_version(v2: QuxV2) -> Self {
+        Self {
+            qux: v2.qux,
+            extra: false,
+        }
+    }
+}
+
+/// This is the latest version.
+type Qux = QuxV3;
+
+#[derive(Debug, PartialEq, GroupDeserialize, GroupSerialize)]
 enum MyGroup1 {
     Foo(Foo),
     Bar(Bar),
@@ -51,6 +116,155 @@ enum MyGroup1 {
 assign_message_ids! {
     Foo: 123,
     Bar: 999,
+    Baz: 456,
+    Qux: 789,
+    Quux: 1000,
+}
+
+// Versions 1 and 2 have been retired, and version 5 was never released.
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize)]
+struct QuuxV3 {
+    quux: u8,
+}
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize)]
+struct QuuxV4 {
+    quux: u16,
+}
+
+impl FromVersion<QuuxV3> for QuuxV4 {
+    fn from_version(v3: QuuxV3) -> Self {
+        Self {
+            quux: v3.quux.into(),
+        }
+    }
+}
+
+impl DowngradeTo<QuuxV3> for QuuxV4 {
+    fn downgrade_to(&self) -> QuuxV3 {
+        QuuxV3 {
+            quux: self.quux as u8,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest, DowngradeTo)]
+#[aversion(versions(3, 4, 6))]
+struct QuuxV6 {
+    quux: u32,
+}
+
+impl FromVersion<QuuxV4> for QuuxV6 {
+    fn from_version(v4: QuuxV4) -> Self {
+        Self {
+            quux: u32::from(v4.quux) * 100,
+        }
+    }
+}
+
+impl DowngradeTo<QuuxV4> for QuuxV6 {
+    fn downgrade_to(&self) -> QuuxV4 {
+        QuuxV4 {
+            quux: (self.quux / 100) as u16,
+        }
+    }
+}
+
+/// This is the latest version.
+type Quux = QuuxV6;
+
+// A message family that doesn't follow the naming convention.
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize)]
+#[aversion(base = Baz, version = 1)]
+struct LegacyBaz {
+    baz: u16,
+}
+
+// Older versions are located by name, so give the legacy struct an alias.
+type BazV1 = LegacyBaz;
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize)]
+struct BazV2 {
+    baz2: u32,
+}
+
+impl FromVersion<LegacyBaz> for BazV2 {
+    fn from_version(v1: LegacyBaz) -> Self {
+        Self {
+            baz2: v1.baz.into(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest)]
+#[aversion(base = Baz, version = 3)]
+struct BazCurrent {
+    baz3: u64,
+}
+
+impl FromVersion<BazV2> for BazCurrent {
+    fn from_version(v2: BazV2) -> Self {
+        Self {
+            baz3: u64::from(v2.baz2) * 2,
+        }
+    }
+}
+
+/// This is the latest version.
+type Baz = BazCurrent;
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest)]
+struct CorgeV1 {
+    corge: String,
+}
+
+/// This is the latest version.
+type Corge = CorgeV1;
+
+/// A group that assigns some message ids itself.
+#[derive(Debug, PartialEq, GroupDeserialize, GroupSerialize)]
+enum MyGroup2 {
+    #[aversion(id = 2000)]
+    Corge(Corge),
+    // This message id is assigned by `assign_message_ids`.
+    Bar(Bar),
+}
+
+/// A group that returns unknown messages to the caller.
+#[derive(Debug, PartialEq, GroupDeserialize)]
+enum MyGroup3 {
+    Foo(Foo),
+    Bar(Bar),
+    #[aversion(unknown)]
+    Unknown(UnknownMessage),
+}
+
+/// A group that skips over unknown messages.
+#[derive(Debug, PartialEq, GroupDeserialize)]
+#[aversion(skip_unknown)]
+enum MyGroup4 {
+    Foo(Foo),
+    Bar(Bar),
+}
+
+/// Write a message with an arbitrary id and version.
+fn write_raw<T: Serialize>(cursor: &mut Cursor<Vec<u8>>, msg_id: u16, msg_ver: u16, msg: &T) {
+    let msg_buf = serde_cbor::to_vec(msg).unwrap();
+    let header = BasicHeader::new(msg_id, msg_ver, msg_buf.len() as u32);
+    header.serialize_into(cursor).unwrap();
+    cursor.write_all(&msg_buf).unwrap();
+}
+
+#[test]
+fn test_basic_header_bytes() {
+    let header = BasicHeader::new(0x1234, 0x5678, 0x9abc_def0);
+    let buf = header.serialize();
+    assert_eq!(buf, [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0]);
+    // `deserialize` accepts anything that implements `AsRef<[u8; 8]>`.
+    let header = BasicHeader::deserialize(Box::new(buf));
+    assert_eq!(header.msg_id, 0x1234);
+    assert_eq!(header.msg_ver, 0x5678);
+    assert_eq!(header.msg_len, 0x9abc_def0);
 }
 
 #[test]
@@ -82,3 +296,996 @@ fn test_group() {
         assert_eq!(message, Foo { foo3: 1245 });
     }
 }
+
+#[test]
+fn test_group_serialize() {
+    let cursor = Cursor::new(Vec::<u8>::new());
+    let mut out_stream = CborData::new(cursor);
+
+    let messages = vec![
+        MyGroup1::Foo(Foo { foo3: 77 }),
+        MyGroup1::Bar(Bar { bar: 88 }),
+        MyGroup1::Foo(Foo { foo3: 99 }),
+    ];
+    for message in &messages {
+        message.write_message(&mut out_stream).unwrap();
+    }
+    let mut cursor = out_stream.into_inner();
+    // Reset the cursor so we will read from the beginning.
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+
+    let mut my_stream = CborData::new(cursor);
+    for expected in messages {
+        let message = MyGroup1::read_message(&mut my_stream).unwrap();
+        assert_eq!(message, expected);
+    }
+}
+
+/// A group that is only written. The message id on the variant is
+/// checked against the id assigned by `assign_message_ids`.
+#[derive(Debug, PartialEq, GroupSerialize)]
+enum WriteOnlyGroup {
+    #[aversion(id = 999)]
+    Bar(Bar),
+    Foo(Foo),
+}
+
+#[test]
+fn test_write_only_group() {
+    let mut out_stream = CborData::new(Vec::new());
+    WriteOnlyGroup::Bar(Bar { bar: 5 })
+        .write_message(&mut out_stream)
+        .unwrap();
+    WriteOnlyGroup::Foo(Foo { foo3: 6 })
+        .write_message(&mut out_stream)
+        .unwrap();
+    let buf = out_stream.into_inner();
+
+    let mut my_stream = CborData::new(buf.as_slice());
+    let message = MyGroup1::read_message(&mut my_stream).unwrap();
+    assert_eq!(message, MyGroup1::Bar(Bar { bar: 5 }));
+    let message = MyGroup1::read_message(&mut my_stream).unwrap();
+    assert_eq!(message, MyGroup1::Foo(Foo { foo3: 6 }));
+}
+
+#[test]
+fn test_variant_message_ids() {
+    assert_eq!(Corge::MSG_ID, 2000);
+
+    let cursor = Cursor::new(Vec::<u8>::new());
+    let mut out_stream = CborData::new(cursor);
+
+    let messages = vec![
+        MyGroup2::Corge(Corge {
+            corge: "hello".to_owned(),
+        }),
+        MyGroup2::Bar(Bar { bar: 88 }),
+    ];
+    for message in &messages {
+        message.write_message(&mut out_stream).unwrap();
+    }
+    let mut cursor = out_stream.into_inner();
+    // Reset the cursor so we will read from the beginning.
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+
+    let mut my_stream = CborData::new(cursor);
+    for expected in messages {
+        let message = MyGroup2::read_message(&mut my_stream).unwrap();
+        assert_eq!(message, expected);
+    }
+}
+
+#[test]
+fn test_attribute_versions() {
+    let cursor = Cursor::new(Vec::<u8>::new());
+    let mut out_stream = CborData::new(cursor);
+
+    out_stream.write_message(&LegacyBaz { baz: 21 }).unwrap();
+    out_stream.write_message(&BazV2 { baz2: 50 }).unwrap();
+    out_stream.write_message(&BazCurrent { baz3: 7 }).unwrap();
+    let mut cursor = out_stream.into_inner();
+    // Reset the cursor so we will read from the beginning.
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+
+    let mut my_stream = CborData::new(cursor);
+    let message: Baz = my_stream.expect_message().unwrap();
+    assert_eq!(message, Baz { baz3: 42 });
+    let message: Baz = my_stream.expect_message().unwrap();
+    assert_eq!(message, Baz { baz3: 100 });
+    let message: Baz = my_stream.expect_message().unwrap();
+    assert_eq!(message, Baz { baz3: 7 });
+}
+
+#[test]
+fn test_fallible_upgrade() {
+    let cursor = Cursor::new(Vec::<u8>::new());
+    let mut out_stream = CborData::new(cursor);
+
+    out_stream.write_message(&QuxV1 { qux: 55 }).unwrap();
+    out_stream.write_message(&QuxV1 { qux: u64::MAX }).unwrap();
+    let mut cursor = out_stream.into_inner();
+    // Reset the cursor so we will read from the beginning.
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+
+    let mut my_stream = CborData::new(cursor);
+    let message: Qux = my_stream.expect_message().unwrap();
+    assert_eq!(
+        message,
+        Qux {
+            qux: 55,
+            extra: false
+        }
+    );
+    let err = my_stream.expect_message::<Qux>().unwrap_err();
+    assert!(matches!(err, CborDataError::Migration { ver: 1, .. }));
+    // The error returned by the upgrade is available as the source.
+    let source = err.source().unwrap();
+    assert!(source.downcast_ref::<TryFromIntError>().is_some());
+}
+
+#[test]
+fn test_downgrade() {
+    let cursor = Cursor::new(Vec::<u8>::new());
+    let mut out_stream = CborData::new(cursor);
+
+    let my_foo = Foo { foo3: 1245 };
+    out_stream.write_message_as::<FooV1>(&my_foo).unwrap();
+    out_stream.write_message_as::<FooV2>(&my_foo).unwrap();
+    let mut cursor = out_stream.into_inner();
+    // Reset the cursor so we will read from the beginning.
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+
+    let mut my_stream = CborData::new(cursor.clone());
+    let message: Foo = my_stream.expect_message().unwrap();
+    assert_eq!(message, my_foo);
+    let message: Foo = my_stream.expect_message().unwrap();
+    assert_eq!(message, my_foo);
+
+    // Verify that the older versions were actually written.
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+    let mut my_stream = CborData::new(cursor);
+    let header = my_stream.read_header().unwrap();
+    assert_eq!(header.msg_ver, 1);
+    let message: FooV1 = my_stream.read_message(&header).unwrap();
+    assert_eq!(message, FooV1 { foo: 1234 });
+    let header = my_stream.read_header().unwrap();
+    assert_eq!(header.msg_ver, 2);
+}
+
+#[test]
+fn test_sparse_versions() {
+    let cursor = Cursor::new(Vec::<u8>::new());
+    let mut out_stream = CborData::new(cursor);
+
+    out_stream.write_message(&QuuxV3 { quux: 1 }).unwrap();
+    out_stream.write_message(&QuuxV4 { quux: 2 }).unwrap();
+    out_stream.write_message(&QuuxV6 { quux: 3 }).unwrap();
+    out_stream
+        .write_message_as::<QuuxV3>(&Quux { quux: 400 })
+        .unwrap();
+    let mut cursor = out_stream.into_inner();
+
+    // Write a message with a retired version number.
+    write_raw(&mut cursor, 1000, 1, &QuuxV3 { quux: 5 });
+
+    // Reset the cursor so we will read from the beginning.
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+
+    let mut my_stream = CborData::new(cursor);
+    let message: Quux = my_stream.expect_message().unwrap();
+    assert_eq!(message, Quux { quux: 100 });
+    let message: Quux = my_stream.expect_message().unwrap();
+    assert_eq!(message, Quux { quux: 200 });
+    let message: Quux = my_stream.expect_message().unwrap();
+    assert_eq!(message, Quux { quux: 3 });
+    let message: Quux = my_stream.expect_message().unwrap();
+    assert_eq!(message, Quux { quux: 400 });
+    let err = my_stream.expect_message::<Quux>().unwrap_err();
+    match err {
+        CborDataError::UnknownVersion { type_name, ver: 1 } => {
+            assert!(type_name.ends_with("QuuxV6"));
+        }
+        _ => panic!("unexpected error {:?}", err),
+    }
+}
+
+/// Write a stream containing an unknown message id and an unknown version.
+fn write_unknown_messages() -> Cursor<Vec<u8>> {
+    let mut cursor = Cursor::new(Vec::<u8>::new());
+    write_raw(&mut cursor, 3333, 1, &"mystery");
+    write_raw(&mut cursor, Foo::MSG_ID, 4, &"from the future");
+    write_raw(&mut cursor, Bar::MSG_ID, 1, &Bar { bar: 44 });
+    // Reset the cursor so we will read from the beginning.
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+    cursor
+}
+
+#[test]
+fn test_unknown_messages() {
+    // By default, unknown messages are errors.
+    let mut my_stream = CborData::new(write_unknown_messages());
+    let err = MyGroup1::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(err, CborDataError::UnknownMessage { id: 3333 }));
+
+    // Unknown messages can be returned as an enum variant.
+    let mut my_stream = CborData::new(write_unknown_messages());
+    let message = MyGroup3::read_message(&mut my_stream).unwrap();
+    assert_eq!(
+        message,
+        MyGroup3::Unknown(UnknownMessage {
+            msg_id: 3333,
+            msg_ver: 1,
+            raw_bytes: serde_cbor::to_vec(&"mystery").unwrap(),
+        })
+    );
+    let message = MyGroup3::read_message(&mut my_stream).unwrap();
+    match message {
+        MyGroup3::Unknown(unknown) => {
+            assert_eq!(unknown.msg_id, Foo::MSG_ID);
+            assert_eq!(unknown.msg_ver, 4);
+        }
+        _ => panic!("expected unknown message"),
+    }
+    let message = MyGroup3::read_message(&mut my_stream).unwrap();
+    assert_eq!(message, MyGroup3::Bar(Bar { bar: 44 }));
+
+    // Unknown messages can be skipped.
+    let mut my_stream = CborData::new(write_unknown_messages());
+    let message = MyGroup4::read_message(&mut my_stream).unwrap();
+    assert_eq!(message, MyGroup4::Bar(Bar { bar: 44 }));
+}
+
+#[test]
+fn test_peek_header() {
+    let cursor = Cursor::new(Vec::<u8>::new());
+    let mut out_stream = CborData::new(cursor);
+
+    out_stream.write_message(&Bar { bar: 1 }).unwrap();
+    out_stream.write_message(&FooV2 { foo2: 2 }).unwrap();
+    let mut cursor = out_stream.into_inner();
+    // Reset the cursor so we will read from the beginning.
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+
+    let mut my_stream = CborData::new(cursor);
+    let header = my_stream.peek_header().unwrap();
+    assert_eq!(header.msg_id, Bar::MSG_ID);
+    assert!(my_stream.next_is::<Bar>().unwrap());
+    assert!(!my_stream.next_is::<Foo>().unwrap());
+    let message = MyGroup1::read_message(&mut my_stream).unwrap();
+    assert_eq!(message, MyGroup1::Bar(Bar { bar: 1 }));
+
+    assert!(my_stream.next_is::<Foo>().unwrap());
+    let message: Foo = my_stream.expect_message().unwrap();
+    assert_eq!(message, Foo { foo3: 12 });
+
+    let mut my_stream = CborData::new(write_unknown_messages());
+    let err = my_stream.expect_message::<Bar>().unwrap_err();
+    match err {
+        CborDataError::UnexpectedMessage {
+            expected,
+            got: 3333,
+        } => {
+            assert!(expected.ends_with("BarV1"));
+        }
+        _ => panic!("unexpected error {:?}", err),
+    }
+}
+
+#[test]
+fn test_messages_iterator() {
+    let cursor = Cursor::new(Vec::<u8>::new());
+    let mut out_stream = CborData::new(cursor);
+    out_stream.write_message(&Bar { bar: 1 }).unwrap();
+    out_stream.write_message(&FooV1 { foo: 2 }).unwrap();
+    let buf = out_stream.into_inner().into_inner();
+
+    // The iterator ends cleanly at the end of the last message.
+    let mut my_stream = CborData::new(buf.as_slice());
+    let messages: Vec<MyGroup1> = my_stream
+        .messages()
+        .collect::<Result<_, CborDataError>>()
+        .unwrap();
+    assert_eq!(
+        messages,
+        vec![
+            MyGroup1::Bar(Bar { bar: 1 }),
+            MyGroup1::Foo(Foo { foo3: 13 })
+        ]
+    );
+
+    // Truncating the data partway through a header is an error.
+    let mut truncated = buf.clone();
+    truncated.extend_from_slice(&[0, 1, 0]);
+    let mut my_stream = CborData::new(truncated.as_slice());
+    let mut messages = my_stream.messages::<MyGroup1>();
+    assert!(messages.next().unwrap().is_ok());
+    assert!(messages.next().unwrap().is_ok());
+    assert!(messages.next().unwrap().is_err());
+    assert!(messages.next().is_none());
+
+    // Truncating the data partway through a message is an error.
+    let truncated = &buf[..buf.len() - 1];
+    let mut my_stream = CborData::new(truncated);
+    let mut messages = my_stream.messages::<MyGroup1>();
+    assert!(messages.next().unwrap().is_ok());
+    assert!(messages.next().unwrap().is_err());
+    assert!(messages.next().is_none());
+}
+
+#[test]
+fn test_messages_skip_unknown() {
+    // A skipped message at the end of the data is still a clean end.
+    let mut cursor = write_unknown_messages();
+    cursor.seek(SeekFrom::End(0)).unwrap();
+    write_raw(&mut cursor, 3333, 1, &"mystery");
+    let buf = cursor.into_inner();
+
+    let mut my_stream = CborData::new(buf.as_slice());
+    let messages: Vec<MyGroup4> = my_stream
+        .messages()
+        .collect::<Result<_, CborDataError>>()
+        .unwrap();
+    assert_eq!(messages, vec![MyGroup4::Bar(Bar { bar: 44 })]);
+}
+
+/// A custom header: a 1-byte id, a 1-byte version, and a 2-byte length.
+#[derive(Debug)]
+struct SmallHeader {
+    msg_id: u8,
+    msg_ver: u8,
+    msg_len: u16,
+}
+
+impl GroupHeader for SmallHeader {
+    fn msg_id(&self) -> u16 {
+        self.msg_id.into()
+    }
+
+    fn msg_ver(&self) -> u16 {
+        self.msg_ver.into()
+    }
+}
+
+impl FramedHeader for SmallHeader {
+    fn from_parts(msg_id: u16, msg_ver: u16, msg_len: u32) -> Self {
+        SmallHeader {
+            msg_id: msg_id.try_into().unwrap(),
+            msg_ver: msg_ver.try_into().unwrap(),
+            msg_len: msg_len.try_into().unwrap(),
+        }
+    }
+
+    fn msg_len(&self) -> Option<u32> {
+        Some(self.msg_len.into())
+    }
+
+    fn deserialize_from(r: &mut impl Read) -> Result<Self, io::Error> {
+        let mut buf = [0u8; 4];
+        r.read_exact(&mut buf)?;
+        Ok(SmallHeader {
+            msg_id: buf[0],
+            msg_ver: buf[1],
+            msg_len: u16::from_le_bytes([buf[2], buf[3]]),
+        })
+    }
+
+    fn serialize_into(&self, w: &mut impl Write) -> Result<(), io::Error> {
+        let len = self.msg_len.to_le_bytes();
+        w.write_all(&[self.msg_id, self.msg_ver, len[0], len[1]])
+    }
+}
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest)]
+struct GraultV1 {
+    grault: i8,
+}
+
+type Grault = GraultV1;
+
+#[derive(Debug, PartialEq, GroupDeserialize)]
+enum SmallGroup {
+    #[aversion(id = 7)]
+    Grault(Grault),
+}
+
+#[test]
+fn test_framed_headers() {
+    // A header without a message length.
+    let mut out_stream = FramedData::<Cbor, TinyHeader, _>::new(Vec::new());
+    out_stream.write_message(&FooV1 { foo: 1 }).unwrap();
+    out_stream.write_message(&Bar { bar: 2 }).unwrap();
+    let buf = out_stream.into_inner();
+
+    let mut my_stream = FramedData::<Cbor, TinyHeader, _>::new(buf.as_slice());
+    let messages: Vec<MyGroup1> = my_stream
+        .messages()
+        .collect::<Result<_, CborDataError>>()
+        .unwrap();
+    assert_eq!(
+        messages,
+        vec![
+            MyGroup1::Foo(Foo { foo3: 12 }),
+            MyGroup1::Bar(Bar { bar: 2 })
+        ]
+    );
+
+    // Unknown messages can't be skipped without the message length.
+    let mut out_stream = FramedData::<Cbor, TinyHeader, _>::new(Vec::new());
+    out_stream.write_message(&Grault { grault: 1 }).unwrap();
+    out_stream.write_message(&Bar { bar: 2 }).unwrap();
+    let buf = out_stream.into_inner();
+    let mut my_stream = FramedData::<Cbor, TinyHeader, _>::new(buf.as_slice());
+    let err = MyGroup4::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(err, CborDataError::UnknownMessage { id: 7 }));
+
+    // A user-defined header.
+    let mut out_stream = FramedData::<Cbor, SmallHeader, _>::new(Vec::new());
+    out_stream.write_message(&Grault { grault: -3 }).unwrap();
+    let buf = out_stream.into_inner();
+    assert_eq!(buf[..2], [7, 1]);
+    assert_eq!(usize::from(buf[2]), buf.len() - 4);
+
+    let mut my_stream = FramedData::<Cbor, SmallHeader, _>::new(buf.as_slice());
+    let message = SmallGroup::read_message(&mut my_stream).unwrap();
+    assert_eq!(message, SmallGroup::Grault(Grault { grault: -3 }));
+    assert!(my_stream.at_eof().unwrap());
+}
+
+#[test]
+fn test_varint_header() {
+    let header = VarintHeader::new(3, 1, 100);
+    assert_eq!(header.serialize(), [3, 1, 100]);
+    let header = VarintHeader::new(300, 0x7fff, 0xffff_ffff);
+    let buf = header.serialize();
+    assert_eq!(
+        buf,
+        [0xac, 0x02, 0xff, 0xff, 0x01, 0xff, 0xff, 0xff, 0xff, 0x0f]
+    );
+    let header = VarintHeader::deserialize_from(&mut buf.as_slice()).unwrap();
+    assert_eq!(header.msg_id, 300);
+    assert_eq!(header.msg_ver, 0x7fff);
+    assert_eq!(header.msg_len, 0xffff_ffff);
+
+    // Values that don't fit in the header fields.
+    let err = VarintHeader::deserialize_from(&mut [0x80, 0x80, 0x04, 0, 0].as_ref()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let err = VarintHeader::deserialize_from(&mut [0x80; 20].as_ref()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let mut out_stream = FramedData::<Cbor, VarintHeader, _>::new(Vec::new());
+    out_stream.write_message(&Grault { grault: -3 }).unwrap();
+    out_stream.write_message(&FooV1 { foo: 1 }).unwrap();
+    out_stream.write_message(&Bar { bar: 2 }).unwrap();
+    let buf = out_stream.into_inner();
+    // The header is only 3 bytes long.
+    let msg_len = serde_cbor::to_vec(&Grault { grault: -3 }).unwrap().len();
+    assert_eq!(buf[..3], [7, 1, msg_len as u8]);
+
+    let mut my_stream = FramedData::<Cbor, VarintHeader, _>::new(buf.as_slice());
+    let messages: Vec<MyGroup4> = my_stream
+        .messages()
+        .collect::<Result<_, CborDataError>>()
+        .unwrap();
+    assert_eq!(
+        messages,
+        vec![
+            MyGroup4::Foo(Foo { foo3: 12 }),
+            MyGroup4::Bar(Bar { bar: 2 })
+        ]
+    );
+}
+
+#[test]
+fn test_checksum_header() {
+    let mut out_stream = FramedData::<Cbor, ChecksumHeader, _>::new(Vec::new());
+    out_stream.write_message(&FooV1 { foo: 1 }).unwrap();
+    out_stream.write_message(&Bar { bar: 2 }).unwrap();
+    let buf = out_stream.into_inner();
+
+    let header = ChecksumHeader::deserialize_from(&mut buf.as_slice()).unwrap();
+    let msg_len = header.msg_len as usize;
+    assert!(header.verify(&buf[12..12 + msg_len]));
+    let rebuilt = ChecksumHeader::new(
+        header.msg_id,
+        header.msg_ver,
+        header.msg_len,
+        &buf[12..12 + msg_len],
+    );
+    assert_eq!(rebuilt.serialize(), buf[..12]);
+
+    let mut my_stream = FramedData::<Cbor, ChecksumHeader, _>::new(buf.as_slice());
+    let messages: Vec<MyGroup1> = my_stream
+        .messages()
+        .collect::<Result<_, CborDataError>>()
+        .unwrap();
+    assert_eq!(
+        messages,
+        vec![
+            MyGroup1::Foo(Foo { foo3: 12 }),
+            MyGroup1::Bar(Bar { bar: 2 })
+        ]
+    );
+
+    // Flip one bit in the message id, the length, the checksum, and the message.
+    for &(index, bit) in &[(1, 0x02), (7, 0x01), (11, 0x80), (12 + msg_len - 1, 0x01)] {
+        let mut corrupt = buf.clone();
+        corrupt[index] ^= bit;
+        let mut my_stream = FramedData::<Cbor, ChecksumHeader, _>::new(corrupt.as_slice());
+        let err = MyGroup4::read_message(&mut my_stream).unwrap_err();
+        assert!(
+            matches!(err, CborDataError::Checksum),
+            "{}: {:?}",
+            index,
+            err
+        );
+    }
+}
+
+#[test]
+fn test_preamble() {
+    const APP_TAG: u32 = 0xa11ce;
+
+    let mut out_stream = PreambleSink::<Cbor, BasicHeader, _>::new(Vec::new(), APP_TAG).unwrap();
+    out_stream.write_message(&FooV1 { foo: 1 }).unwrap();
+    out_stream.write_message(&Bar { bar: 2 }).unwrap();
+    let buf = out_stream.into_inner().into_inner();
+    assert_eq!(buf[..8], *b"AVERSION");
+
+    let mut my_stream =
+        PreambleSource::<Cbor, BasicHeader, _>::new(buf.as_slice(), APP_TAG).unwrap();
+    assert_eq!(
+        *my_stream.preamble(),
+        Preamble {
+            framing_version: 1,
+            header_kind: 2,
+            app_tag: APP_TAG,
+        }
+    );
+    let messages: Vec<MyGroup1> = my_stream
+        .messages()
+        .collect::<Result<_, CborDataError>>()
+        .unwrap();
+    assert_eq!(
+        messages,
+        vec![
+            MyGroup1::Foo(Foo { foo3: 12 }),
+            MyGroup1::Bar(Bar { bar: 2 })
+        ]
+    );
+
+    // An empty stream still has a preamble.
+    let out_stream = PreambleSink::<Cbor, BasicHeader, _>::new(Vec::new(), APP_TAG).unwrap();
+    let empty = out_stream.into_inner().into_inner();
+    let mut my_stream =
+        PreambleSource::<Cbor, BasicHeader, _>::new(empty.as_slice(), APP_TAG).unwrap();
+    assert!(my_stream.at_eof().unwrap());
+
+    // Message limits apply after the preamble.
+    let mut my_stream = PreambleSource::<Cbor, BasicHeader, _>::new(buf.as_slice(), APP_TAG)
+        .unwrap()
+        .with_limits(MessageLimits::new().with_max_len(1));
+    let err = MyGroup1::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(err, CborDataError::TooLarge { max_len: 1, .. }));
+
+    let mut out_stream = PreambleSink::<Cbor, BasicHeader, _>::new(Vec::new(), APP_TAG)
+        .unwrap()
+        .with_limits(MessageLimits::new().with_max_len(1));
+    let err = out_stream.write_message(&Bar { bar: 2 }).unwrap_err();
+    assert!(matches!(err, CborDataError::TooLarge { max_len: 1, .. }));
+
+    let err = PreambleSource::<Cbor, BasicHeader, _>::new(buf.as_slice(), 7)
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err,
+        PreambleError::AppTag {
+            expected: 7,
+            found: APP_TAG
+        }
+    ));
+
+    let err = PreambleSource::<Cbor, VarintHeader, _>::new(buf.as_slice(), APP_TAG)
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err,
+        PreambleError::HeaderKind {
+            expected: 4,
+            found: 2
+        }
+    ));
+
+    let mut future = buf.clone();
+    future[9] = 2;
+    let err = PreambleSource::<Cbor, BasicHeader, _>::new(future.as_slice(), APP_TAG)
+        .err()
+        .unwrap();
+    assert!(matches!(err, PreambleError::Version(2)));
+
+    // Data without a preamble.
+    let err = PreambleSource::<Cbor, BasicHeader, _>::new(&buf[16..], APP_TAG)
+        .err()
+        .unwrap();
+    assert!(matches!(err, PreambleError::Magic));
+    let err = PreambleSource::<Cbor, BasicHeader, _>::new(&buf[..4], APP_TAG)
+        .err()
+        .unwrap();
+    assert!(matches!(err, PreambleError::Io(_)));
+}
+
+#[test]
+fn test_message_limits() {
+    let limits = MessageLimits::new()
+        .with_max_len(8)
+        .with_max_len_for(Baz::MSG_ID, 1000);
+    let long_baz = Baz { baz3: u64::MAX };
+
+    // Writing a message that's too long is an error, and writes nothing.
+    let mut out_stream = CborData::new(Vec::new()).with_limits(limits.clone());
+    out_stream.write_message(&Bar { bar: 1 }).unwrap();
+    let err = out_stream
+        .write_message(&Quux { quux: u32::MAX })
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        CborDataError::TooLarge {
+            msg_id: 1000,
+            max_len: 8
+        }
+    ));
+    out_stream.write_message(&long_baz).unwrap();
+    let buf = out_stream.into_inner();
+
+    let mut my_stream = CborData::new(buf.as_slice()).with_limits(limits.clone());
+    let message = MyGroup1::read_message(&mut my_stream).unwrap();
+    assert_eq!(message, MyGroup1::Bar(Bar { bar: 1 }));
+    let message: Baz = my_stream.expect_message().unwrap();
+    assert_eq!(message, long_baz);
+
+    // Reading a message that's too long is an error, even if it would be skipped.
+    let mut my_stream =
+        CborData::new(buf.as_slice()).with_limits(MessageLimits::new().with_max_len(8));
+    let message = MyGroup4::read_message(&mut my_stream).unwrap();
+    assert_eq!(message, MyGroup4::Bar(Bar { bar: 1 }));
+    let err = MyGroup4::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(
+        err,
+        CborDataError::TooLarge {
+            msg_id: 456,
+            max_len: 8
+        }
+    ));
+
+    // A header without a length can't be checked until the message is read.
+    let mut out_stream = FramedData::<Cbor, TinyHeader, _>::new(Vec::new());
+    out_stream.write_message(&long_baz).unwrap();
+    let buf = out_stream.into_inner();
+    let mut my_stream = FramedData::<Cbor, TinyHeader, _>::new(buf.as_slice())
+        .with_limits(MessageLimits::new().with_max_len(8));
+    let err = my_stream.expect_message::<Baz>().unwrap_err();
+    assert!(matches!(err, CborDataError::TooLarge { msg_id: 456, .. }));
+}
+
+/// A small pseudo-random number generator (xorshift).
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+#[test]
+fn test_hostile_headers() {
+    const MAX_LEN: u32 = 256;
+    let limits = MessageLimits::new().with_max_len(MAX_LEN);
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+
+    for _ in 0..2000 {
+        // A header with a mostly-valid message id and version, and a
+        // random length, followed by random data.
+        let msg_id = [123, 999, 456, 789, 1000, rng.next() as u16][rng.next() as usize % 6];
+        let msg_ver = (rng.next() % 4) as u16;
+        let msg_len = match rng.next() % 3 {
+            0 => rng.next() as u32,
+            1 => u32::MAX - (rng.next() % 16) as u32,
+            _ => (rng.next() % 64) as u32,
+        };
+        let mut buf = BasicHeader::new(msg_id, msg_ver, msg_len)
+            .serialize()
+            .to_vec();
+        let body_len = (rng.next() % 80) as usize;
+        buf.extend(rng.bytes(body_len));
+
+        let mut my_stream = CborData::new(buf.as_slice()).with_limits(limits.clone());
+        let result = MyGroup4::read_next(&mut my_stream);
+        if msg_len > MAX_LEN {
+            assert!(
+                matches!(
+                    result,
+                    Err(CborDataError::TooLarge {
+                        max_len: MAX_LEN,
+                        ..
+                    })
+                ),
+                "{:?}",
+                result
+            );
+        }
+
+        // Every header type must survive random data, without panicking
+        // or reading past the limit.
+        let mut my_stream = CborData::new(buf.as_slice()).with_limits(limits.clone());
+        let _ = my_stream.messages::<MyGroup3>().count();
+        let mut my_stream =
+            FramedData::<Cbor, TinyHeader, _>::new(buf.as_slice()).with_limits(limits.clone());
+        let _ = my_stream.messages::<MyGroup3>().count();
+        let mut my_stream =
+            FramedData::<Cbor, VarintHeader, _>::new(buf.as_slice()).with_limits(limits.clone());
+        let _ = my_stream.messages::<MyGroup3>().count();
+        let mut my_stream =
+            FramedData::<Cbor, ChecksumHeader, _>::new(buf.as_slice()).with_limits(limits.clone());
+        let _ = my_stream.messages::<MyGroup3>().count();
+    }
+}
+
+#[test]
+fn test_corrupt_message() {
+    let mut buf = Vec::new();
+    // A message body that isn't valid CBOR.
+    BasicHeader::new(123, 1, 4)
+        .serialize_into(&mut buf)
+        .unwrap();
+    buf.extend_from_slice(&[0xff; 4]);
+    // A valid message, followed by extra bytes.
+    let msg_buf = serde_cbor::to_vec(&Bar { bar: 1 }).unwrap();
+    BasicHeader::new(999, 1, msg_buf.len() as u32 + 3)
+        .serialize_into(&mut buf)
+        .unwrap();
+    buf.extend_from_slice(&msg_buf);
+    buf.extend_from_slice(&[1, 2, 3]);
+    let mut cursor = Cursor::new(buf);
+    cursor.seek(SeekFrom::End(0)).unwrap();
+    write_raw(&mut cursor, 999, 1, &Bar { bar: 2 });
+    let buf = cursor.into_inner();
+
+    // Each message is consumed, even if it can't be deserialized.
+    let mut my_stream = CborData::new(buf.as_slice());
+    let err = MyGroup1::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(
+        err,
+        CborDataError::Decode {
+            id: 123,
+            ver: 1,
+            ..
+        }
+    ));
+    let source = err.source().unwrap();
+    assert!(source.downcast_ref::<serde_cbor::Error>().is_some());
+    // Extra bytes after a message are an error.
+    let err = MyGroup1::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(
+        err,
+        CborDataError::Decode {
+            id: 999,
+            ver: 1,
+            ..
+        }
+    ));
+    let message = MyGroup1::read_message(&mut my_stream).unwrap();
+    assert_eq!(message, MyGroup1::Bar(Bar { bar: 2 }));
+    assert!(my_stream.at_eof().unwrap());
+
+    // A truncated message is still an error.
+    let mut my_stream = CborData::new(&buf[..buf.len() - 1]);
+    assert!(MyGroup1::read_message(&mut my_stream).is_err());
+    assert!(MyGroup1::read_message(&mut my_stream).is_err());
+    let err = MyGroup1::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(err, CborDataError::Eof));
+}
+
+#[test]
+fn test_truncated_data() {
+    // A message body is truncated, with a header that contains the length.
+    let mut out_stream = CborData::new(Vec::new());
+    out_stream.write_message(&Bar { bar: 1000 }).unwrap();
+    let buf = out_stream.into_inner();
+    let mut my_stream = CborData::new(&buf[..buf.len() - 1]);
+    let err = MyGroup1::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(err, CborDataError::Eof), "{:?}", err);
+
+    // A message body is truncated, with a header that doesn't contain the length.
+    let mut out_stream = FramedData::<Cbor, TinyHeader, _>::new(Vec::new());
+    out_stream.write_message(&Bar { bar: 1000 }).unwrap();
+    let buf = out_stream.into_inner();
+    let mut my_stream = FramedData::<Cbor, TinyHeader, _>::new(&buf[..buf.len() - 1]);
+    let err = MyGroup1::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(err, CborDataError::Eof), "{:?}", err);
+
+    // A header is truncated.
+    let mut my_stream = CborData::new(&buf[..3]);
+    let err = MyGroup1::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(err, CborDataError::Eof), "{:?}", err);
+    let mut my_stream = CborData::new(&buf[..3]);
+    let err = my_stream.at_eof().unwrap_err();
+    assert!(matches!(err, CborDataError::Eof), "{:?}", err);
+}
+
+#[test]
+fn test_resync() {
+    let mut out_stream = FramedData::<Cbor, MarkedHeader, _>::new(Vec::new());
+    out_stream.write_message(&Bar { bar: 3 }).unwrap();
+    let last = out_stream.into_inner();
+
+    let mut buf = Vec::new();
+    let mut out_stream = FramedData::<Cbor, MarkedHeader, _>::new(&mut buf);
+    out_stream.write_message(&FooV1 { foo: 1 }).unwrap();
+    out_stream.write_message(&Bar { bar: 2 }).unwrap();
+    // Garbage between messages, including part of a sync marker.
+    buf.extend_from_slice(&[0xa5, 0x5a, 0xa5, 0x5a, 0xc3, 0, 0]);
+    buf.extend_from_slice(&last);
+
+    // Corrupt the length of the first message.
+    buf[11] ^= 0x40;
+    let mut my_stream = FramedData::<Cbor, MarkedHeader, _>::new(buf.as_slice());
+    let err = MyGroup1::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(err, CborDataError::Io(_)));
+    // The corrupt header and its message are discarded.
+    let foo_len = serde_cbor::to_vec(&FooV1 { foo: 1 }).unwrap().len();
+    assert_eq!(my_stream.resync().unwrap() as usize, 16 + foo_len);
+    let message = MyGroup1::read_message(&mut my_stream).unwrap();
+    assert_eq!(message, MyGroup1::Bar(Bar { bar: 2 }));
+
+    // The garbage can't be read as a header.
+    assert!(MyGroup1::read_message(&mut my_stream).is_err());
+    assert_eq!(my_stream.resync().unwrap(), 7);
+    let message = MyGroup1::read_message(&mut my_stream).unwrap();
+    assert_eq!(message, MyGroup1::Bar(Bar { bar: 3 }));
+
+    // Nothing else is found at the end of the data.
+    assert_eq!(my_stream.resync().unwrap(), 0);
+    assert!(my_stream.at_eof().unwrap());
+
+    // A message that's too long is discarded.
+    let mut buf = Vec::new();
+    let mut out_stream = FramedData::<Cbor, MarkedHeader, _>::new(&mut buf);
+    out_stream.write_message(&FooV1 { foo: 1 }).unwrap();
+    buf.extend_from_slice(&last);
+    let limits = MessageLimits::new().with_max_len_for(Foo::MSG_ID, 1);
+    let mut my_stream =
+        FramedData::<Cbor, MarkedHeader, _>::new(buf.as_slice()).with_limits(limits);
+    let err = MyGroup1::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(err, CborDataError::TooLarge { msg_id: 123, .. }));
+    assert_eq!(my_stream.resync().unwrap() as usize, 16 + foo_len);
+    let message = MyGroup1::read_message(&mut my_stream).unwrap();
+    assert_eq!(message, MyGroup1::Bar(Bar { bar: 3 }));
+
+    // A partial header at the end of the data is discarded.
+    let mut my_stream = FramedData::<Cbor, MarkedHeader, _>::new(&last[..10]);
+    assert!(MyGroup1::read_message(&mut my_stream).is_err());
+    assert_eq!(my_stream.resync().unwrap(), 10);
+    assert!(my_stream.at_eof().unwrap());
+}
+
+/// A user-defined error type, that can hold an `aversion::Error`.
+#[derive(Debug, thiserror::Error)]
+enum SimpleError {
+    #[error("IO Error")]
+    Io(#[from] io::Error),
+    #[error("CBOR Error")]
+    Cbor(#[from] serde_cbor::Error),
+    #[error("Group Error")]
+    Group(#[from] aversion::Error),
+}
+
+/// A `DataSource` that uses the default error functions.
+struct SimpleSource<R> {
+    inner: R,
+}
+
+impl<R: Read> DataSource for SimpleSource<R> {
+    type Error = SimpleError;
+    type Header = BasicHeader;
+
+    fn read_header(&mut self) -> Result<BasicHeader, SimpleError> {
+        Ok(BasicHeader::deserialize_from(&mut self.inner)?)
+    }
+
+    fn read_message<T>(&mut self, header: &BasicHeader) -> Result<T, SimpleError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let reader = (&mut self.inner).take(header.msg_len.into());
+        Ok(serde_cbor::from_reader(reader)?)
+    }
+}
+
+#[test]
+fn test_default_errors() {
+    let mut my_stream = SimpleSource {
+        inner: write_unknown_messages(),
+    };
+    let err = MyGroup1::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(
+        err,
+        SimpleError::Group(aversion::Error::UnknownMessage { msg_id: 3333 })
+    ));
+
+    let mut my_stream = SimpleSource {
+        inner: write_unknown_messages(),
+    };
+    let err = my_stream.expect_message::<Bar>().unwrap_err();
+    match err {
+        SimpleError::Group(aversion::Error::UnexpectedMessage {
+            expected,
+            msg_id: 3333,
+        }) => {
+            assert!(expected.ends_with("BarV1"));
+        }
+        _ => panic!("unexpected error {:?}", err),
+    }
+}
+
+/// A message that implements `UpgradeLatest` by hand. This must compile
+/// whichever features of `aversion` are enabled.
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize)]
+struct GarplyV1 {
+    garply: u8,
+}
+
+type Garply = GarplyV1;
+
+impl MessageId for Garply {
+    const MSG_ID: u16 = 3000;
+}
+
+impl UpgradeLatestTrait for Garply {
+    fn upgrade_latest<Src>(src: &mut Src, header: Src::Header) -> Result<Self, Src::Error>
+    where
+        Src: DataSource,
+    {
+        match header.msg_ver() {
+            1 => src.read_message(&header),
+            ver => Err(src.unknown_version::<Garply>(ver)),
+        }
+    }
+}
+
+/// A message group that implements `GroupDeserialize` by hand.
+#[derive(Debug, PartialEq)]
+enum HandwrittenGroup {
+    Garply(Garply),
+}
+
+impl GroupDeserialize for HandwrittenGroup {
+    fn read_message<Src>(src: &mut Src) -> Result<Self, Src::Error>
+    where
+        Src: DataSource,
+    {
+        let header = src.read_header()?;
+        match header.msg_id() {
+            Garply::MSG_ID => Ok(HandwrittenGroup::Garply(Garply::upgrade_latest(
+                src, header,
+            )?)),
+            msg_id => Err(src.unknown_message(msg_id)),
+        }
+    }
+}
+
+#[test]
+fn test_handwritten_impls() {
+    let mut out_stream = CborData::new(Vec::new());
+    out_stream.write_message(&Garply { garply: 9 }).unwrap();
+    let buf = out_stream.into_inner();
+
+    let mut my_stream = CborData::new(buf.as_slice());
+    let message = HandwrittenGroup::read_message(&mut my_stream).unwrap();
+    assert_eq!(message, HandwrittenGroup::Garply(Garply { garply: 9 }));
+}
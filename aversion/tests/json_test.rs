@@ -0,0 +1,208 @@
+use aversion::group::{DataSink, DataSource, DataSourceExt, PeekHeader, UnknownMessage};
+use aversion::util::framed::MessageLimits;
+use aversion::util::json::{JsonLinesData, JsonLinesDataError};
+use aversion::{
+    assign_message_ids, FromVersion, GroupDeserialize, GroupSerialize, UpgradeLatest, Versioned,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize)]
+struct FooV1 {
+    foo: u32,
+}
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest)]
+struct FooV2 {
+    foo2: u32,
+    label: String,
+}
+
+impl FromVersion<FooV1> for FooV2 {
+    fn from_version(v1: FooV1) -> Self {
+        Self {
+            foo2: v1.foo + 1,
+            label: String::new(),
+        }
+    }
+}
+
+/// This is the latest version.
+type Foo = FooV2;
+
+#[derive(Debug, PartialEq, Versioned, Serialize, Deserialize, UpgradeLatest)]
+struct BarV1 {
+    bar: Vec<u8>,
+}
+
+/// This is the latest version.
+type Bar = BarV1;
+
+assign_message_ids! {
+    Foo: 123,
+    Bar: 999,
+}
+
+#[derive(Debug, PartialEq, GroupDeserialize, GroupSerialize)]
+enum MyGroup {
+    Foo(Foo),
+    Bar(Bar),
+}
+
+#[derive(Debug, PartialEq, GroupDeserialize)]
+enum OnlyBar {
+    Bar(Bar),
+    #[aversion(unknown)]
+    Unknown(UnknownMessage),
+}
+
+#[test]
+fn test_output_format() {
+    let mut out_stream = JsonLinesData::new(Vec::<u8>::new());
+    out_stream.write_message(&FooV1 { foo: 1 }).unwrap();
+    out_stream.write_message(&Bar { bar: vec![1, 2] }).unwrap();
+    let text = String::from_utf8(out_stream.into_inner()).unwrap();
+    assert_eq!(
+        text,
+        "{\"id\":123,\"ver\":1,\"msg\":{\"foo\":1}}\n\
+         {\"id\":999,\"ver\":1,\"msg\":{\"bar\":[1,2]}}\n"
+    );
+}
+
+#[test]
+fn test_round_trip() {
+    let messages = vec![
+        MyGroup::Foo(Foo {
+            foo2: 7,
+            label: "seven".to_owned(),
+        }),
+        MyGroup::Bar(Bar { bar: vec![3] }),
+    ];
+    let mut out_stream = JsonLinesData::new(Vec::<u8>::new());
+    for message in &messages {
+        message.write_message(&mut out_stream).unwrap();
+    }
+    let buf = out_stream.into_inner();
+
+    let mut my_stream = JsonLinesData::new(buf.as_slice());
+    let received: Vec<MyGroup> = my_stream
+        .messages()
+        .collect::<Result<_, JsonLinesDataError>>()
+        .unwrap();
+    assert_eq!(received, messages);
+}
+
+#[test]
+fn test_handwritten() {
+    // Lines written by some other tool, in any field order, with blank lines.
+    let text = r#"
+{"ver": 1, "id": 123, "msg": {"foo": 41}}
+
+{"id": 123, "ver": 2, "msg": {"label": "x", "foo2": 1}}
+{"id": 555, "ver": 1, "msg": [1, 2, 3]}
+"#;
+    let mut my_stream = JsonLinesData::new(text.as_bytes());
+    let header = my_stream.peek_header().unwrap();
+    assert_eq!((header.msg_id, header.msg_ver), (123, 1));
+
+    let message: Foo = my_stream.expect_message().unwrap();
+    assert_eq!(
+        message,
+        Foo {
+            foo2: 42,
+            label: String::new(),
+        }
+    );
+    let message = MyGroup::read_message(&mut my_stream).unwrap();
+    assert_eq!(
+        message,
+        MyGroup::Foo(Foo {
+            foo2: 1,
+            label: "x".to_owned(),
+        })
+    );
+    let err = MyGroup::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(
+        err,
+        JsonLinesDataError::Group(aversion::Error::UnknownMessage { msg_id: 555 })
+    ));
+    assert!(my_stream.messages::<MyGroup>().next().is_none());
+}
+
+#[test]
+fn test_unknown_messages() {
+    let text = "{\"id\":555,\"ver\":1,\"msg\":[1,2,3]}\n\
+                {\"id\":999,\"ver\":1,\"msg\":{\"bar\":[]}}\n\
+                not json\n";
+    let mut my_stream = JsonLinesData::new(text.as_bytes());
+    let err = MyGroup::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(
+        err,
+        JsonLinesDataError::Group(aversion::Error::UnknownMessage { msg_id: 555 })
+    ));
+
+    let mut my_stream = JsonLinesData::new(text.as_bytes());
+    let message = OnlyBar::read_message(&mut my_stream).unwrap();
+    assert_eq!(
+        message,
+        OnlyBar::Unknown(UnknownMessage {
+            msg_id: 555,
+            msg_ver: 1,
+            raw_bytes: b"[1,2,3]".to_vec(),
+        })
+    );
+    let message = OnlyBar::read_message(&mut my_stream).unwrap();
+    assert_eq!(message, OnlyBar::Bar(Bar { bar: vec![] }));
+    let err = OnlyBar::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(err, JsonLinesDataError::Format(_)));
+}
+
+#[test]
+fn test_limits() {
+    let limits = MessageLimits::new()
+        .with_max_len(40)
+        .with_max_len_for(999, 36);
+    let text = format!(
+        "{{\"id\":555,\"ver\":1,\"msg\":\"{}\"}}\n\
+         {{\"id\":999,\"ver\":1,\"msg\":{{\"bar\":[1,2,3]}}}}\n\
+         {{\"id\":999,\"ver\":1,\"msg\":{{\"bar\":[]}}}}\n",
+        "x".repeat(100)
+    );
+    let mut my_stream = JsonLinesData::new(text.as_bytes()).with_limits(limits.clone());
+
+    // The line is longer than every limit, so it isn't parsed.
+    let err = OnlyBar::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(
+        err,
+        JsonLinesDataError::TooLarge {
+            msg_id: 0,
+            max_len: 40
+        }
+    ));
+    // This line is shorter than the default limit, but not the limit for its id.
+    let err = OnlyBar::read_message(&mut my_stream).unwrap_err();
+    assert!(matches!(
+        err,
+        JsonLinesDataError::TooLarge {
+            msg_id: 999,
+            max_len: 36
+        }
+    ));
+    // The long lines were skipped.
+    let message = OnlyBar::read_message(&mut my_stream).unwrap();
+    assert_eq!(message, OnlyBar::Bar(Bar { bar: vec![] }));
+    assert!(my_stream.at_eof().unwrap());
+
+    // Writing a message that is too long fails, without writing anything.
+    let mut out_stream = JsonLinesData::new(Vec::<u8>::new()).with_limits(limits);
+    let err = out_stream
+        .write_message(&Bar { bar: vec![1, 2, 3] })
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        JsonLinesDataError::TooLarge {
+            msg_id: 999,
+            max_len: 36
+        }
+    ));
+    assert!(out_stream.into_inner().is_empty());
+}